@@ -24,7 +24,7 @@ use patina_ffs_extractors::CompositeSectionExtractor;
 use patina_stacktrace::StackTrace;
 #[cfg(feature = "exit_on_patina_test_failure")]
 use qemu_exit::QEMUExit;
-use qemu_resources::armvirt::component::service as armvirt_services;
+use qemu_resources::{armvirt::component::service as armvirt_services, banner::StartupBanner};
 extern crate alloc;
 
 #[panic_handler]
@@ -57,6 +57,9 @@ const _ENABLE_DEBUGGER: bool = true;
 #[cfg(not(feature = "enable_debugger"))]
 const _ENABLE_DEBUGGER: bool = false;
 
+/// Platform name reported in the startup banner.
+const PLATFORM_NAME: &str = "QEMU Arm Virt";
+
 /// Base address of the PL011 UART on the QEMU Arm Virt machine.
 const PL011_UART_BASE: usize = 0x0900_0000;
 
@@ -135,6 +138,6 @@ pub unsafe extern "efiapi" fn _start(physical_hob_list: *const c_void) -> ! {
     #[cfg(feature = "build_debugger")]
     patina_debugger::set_debugger(&DEBUGGER);
 
-    log::info!("DXE Core Platform Binary v{}", env!("CARGO_PKG_VERSION"));
+    StartupBanner::new(PLATFORM_NAME, physical_hob_list).log();
     CORE.entry_point(physical_hob_list)
 }
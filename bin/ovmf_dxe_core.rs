@@ -18,7 +18,7 @@ use patina::{
 use patina_dxe_core::*;
 use patina_ffs_extractors::CompositeSectionExtractor;
 use patina_stacktrace::StackTrace;
-use qemu_resources::q35::timer;
+use qemu_resources::{banner::StartupBanner, q35::timer};
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
@@ -45,6 +45,7 @@ static LOGGER: SerialLogger<Uart16550> = SerialLogger::new(
     Uart16550::Io { base: 0x402 },
 );
 
+const PLATFORM_NAME: &str = "OVMF";
 const PM_TIMER_PORT: u16 = 0x608;
 const _ENABLE_DEBUGGER: bool = cfg!(feature = "enable_debugger");
 
@@ -59,12 +60,21 @@ struct Ovmf;
 // Default `MemoryInfo` implementation is sufficient for OVMF.
 impl MemoryInfo for Ovmf {}
 
-// OVMF should use TSC frequency calibrated from ACPI PM Timer.
+// OVMF should use TSC frequency calibrated from ACPI PM Timer, falling back to the HPET.
 impl CpuInfo for Ovmf {
     fn perf_timer_frequency() -> Option<u64> {
-        // SAFETY: Reading from the PM Timer I/O port is safe as long as the port is valid.
-        // On OVMF, the PM Timer is always available at the specified port address.
-        Some(unsafe { timer::calibrate_tsc_frequency(PM_TIMER_PORT) })
+        // SAFETY: Reading from the PM Timer I/O port and HPET registers is safe as long as they are valid.
+        // On OVMF, the PM Timer is always available at the specified port address and the HPET is mapped at
+        // its fixed MMIO address.
+        Some(unsafe {
+            timer::cached_tsc_frequency(
+                &[
+                    timer::CalibrationSource::PmTimer(PM_TIMER_PORT),
+                    timer::CalibrationSource::Hpet(timer::HPET_BASE_ADDRESS as *const u8),
+                ],
+                1,
+            )
+        })
     }
 }
 
@@ -96,6 +106,6 @@ pub unsafe extern "efiapi" fn _start(physical_hob_list: *const c_void) -> ! {
     #[cfg(feature = "build_debugger")]
     patina_debugger::set_debugger(&DEBUGGER);
 
-    log::info!("DXE Core Platform Binary v{}", env!("CARGO_PKG_VERSION"));
+    StartupBanner::new(PLATFORM_NAME, physical_hob_list).log();
     CORE.entry_point(physical_hob_list)
 }
@@ -19,7 +19,7 @@ use patina_adv_logger::{
 use patina_dxe_core::*;
 use patina_ffs_extractors::CompositeSectionExtractor;
 use patina_stacktrace::StackTrace;
-use qemu_resources::q35::component::service as q35_services;
+use qemu_resources::{banner::StartupBanner, q35::component::service as q35_services};
 extern crate alloc;
 use alloc::vec;
 #[cfg(feature = "exit_on_patina_test_failure")]
@@ -39,10 +39,19 @@ fn panic(info: &PanicInfo) -> ! {
     loop {}
 }
 
+/// Platform name reported in the startup banner.
+const PLATFORM_NAME: &str = "QEMU Q35";
+
 /// Port address of the ACPI PM Timer.
 /// Obtained from ACPI FADT `X_PM_TIMER_BLOCK`. It is always at 0x608 on Q35.
 const PM_TIMER_PORT: u16 = 0x608;
 
+/// Number of TSC calibration samples to average. Each sample adds about 50 ms to boot.
+#[cfg(feature = "accurate_tsc_calibration")]
+const TSC_CALIBRATION_SAMPLES: usize = 5;
+#[cfg(not(feature = "accurate_tsc_calibration"))]
+const TSC_CALIBRATION_SAMPLES: usize = 1;
+
 static LOGGER: AdvancedLogger<Uart16550> = AdvancedLogger::new(
     Format::Standard,
     &[
@@ -74,12 +83,28 @@ struct Q35;
 // Default `MemoryInfo` implementation is sufficient for Q35.
 impl MemoryInfo for Q35 {}
 
-// Q35 should use TSC frequency calibrated from ACPI PM Timer.
+// Q35 should use TSC frequency calibrated from ACPI PM Timer, falling back to the HPET.
 impl CpuInfo for Q35 {
     fn perf_timer_frequency() -> Option<u64> {
-        // SAFETY: Reading from the PM Timer I/O port is safe as long as the port is valid.
-        // On Q35, the PM Timer is always available at the specified port address.
-        Some(unsafe { timer::calibrate_tsc_frequency(PM_TIMER_PORT) })
+        Some(tsc_frequency())
+    }
+}
+
+/// Returns the TSC frequency, calibrating it on first use.
+///
+/// The result is shared by `perf_timer_frequency` and the timer frequency service so calibration only runs once.
+fn tsc_frequency() -> u64 {
+    // SAFETY: Reading from the PM Timer I/O port and HPET registers is safe as long as they are valid.
+    // On Q35, the PM Timer is always available at the specified port address and the HPET is mapped at
+    // its fixed MMIO address.
+    unsafe {
+        timer::cached_tsc_frequency(
+            &[
+                timer::CalibrationSource::PmTimer(PM_TIMER_PORT),
+                timer::CalibrationSource::Hpet(timer::HPET_BASE_ADDRESS as *const u8),
+            ],
+            TSC_CALIBRATION_SAMPLES,
+        )
     }
 }
 
@@ -97,11 +122,17 @@ impl ComponentInfo for Q35 {
 
     fn components(mut add: Add<Component>) {
         add.component(AdvancedLoggerComponent::<Uart16550>::new(&LOGGER));
+        add.component(q35_services::board_variant::QemuQ35BoardVariantSelector::new());
         add.component(q35_services::mm_config_provider::MmConfigurationProvider);
         add.component(q35_services::mm_control::QemuQ35PlatformMmControl::new());
         add.component(patina_mm::component::sw_mmi_manager::SwMmiManager::new());
         add.component(patina_mm::component::communicator::MmCommunicator::new());
+        add.component(q35_services::smm_verify::SmmModeVerifier::new());
         add.component(q35_services::mm_test::QemuQ35MmTest::new());
+        add.component(q35_services::timer_frequency::TimerFrequencyService::new(
+            tsc_frequency(),
+            timer::DEFAULT_ACPI_TIMER_FREQUENCY,
+        ));
         add.component(patina_performance::component::Performance::new().with_measurements(
             patina::performance::Measurement::DriverBindingStart     // Adds driver binding start measurements.
                | patina::performance::Measurement::DriverBindingStop // Adds driver binding stop measurements.
@@ -111,6 +142,16 @@ impl ComponentInfo for Q35 {
         add.component(patina_smbios::component::SmbiosProvider::new(3, 9));
         add.component(q35_services::smbios_platform::Q35SmbiosPlatform::new());
         add.component(patina_acpi::component::AcpiComponent::default());
+        #[cfg(feature = "ipmi_kcs")]
+        add.component(q35_services::ipmi::QemuQ35IpmiKcs::new());
+        add.component(q35_services::platform_reset::QemuQ35PlatformReset::new());
+        add.component(q35_services::power_button::QemuQ35PowerButton::new());
+        #[cfg(feature = "iommu_dma_protection")]
+        add.component(q35_services::iommu::QemuQ35Iommu::new());
+        #[cfg(feature = "s3_support")]
+        add.component(q35_services::s3_resume::QemuQ35S3Support::new());
+        #[cfg(feature = "qmp_client")]
+        add.component(q35_services::qmp_client::QemuQmpClient::new());
         add.component(patina_test::component::TestRunner::default().with_callback(|test_name, err_msg| {
             log::error!("Test {} failed: {}", test_name, err_msg);
             #[cfg(feature = "exit_on_patina_test_failure")]
@@ -146,6 +187,6 @@ pub unsafe extern "efiapi" fn _start(physical_hob_list: *const c_void) -> ! {
     #[cfg(feature = "build_debugger")]
     patina_debugger::set_debugger(&DEBUGGER);
 
-    log::info!("DXE Core Platform Binary v{}", env!("CARGO_PKG_VERSION"));
+    StartupBanner::new(PLATFORM_NAME, physical_hob_list).log();
     CORE.entry_point(physical_hob_list)
 }
@@ -0,0 +1,65 @@
+//! DXE Core Startup Banner
+//!
+//! Provides the startup banner logged by each QEMU DXE core binary before the DXE core entry point runs.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+use core::{ffi::c_void, fmt};
+
+/// Startup banner describing the platform binary, its build, and the physical HOB list it was started with.
+///
+/// The commit and build date are taken from the `GIT_COMMIT` and `BUILD_DATE` environment variables at build time and
+/// are reported as `unknown` if they are not set.
+#[derive(Debug, Clone, Copy)]
+pub struct StartupBanner {
+    platform_name: &'static str,
+    physical_hob_list: *const c_void,
+}
+
+impl StartupBanner {
+    /// Creates the startup banner for `platform_name` started with `physical_hob_list`.
+    pub const fn new(platform_name: &'static str, physical_hob_list: *const c_void) -> Self {
+        Self { platform_name, physical_hob_list }
+    }
+
+    /// Logs the startup banner.
+    pub fn log(&self) {
+        log::info!("{self}");
+    }
+}
+
+impl fmt::Display for StartupBanner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DXE Core Platform Binary v{} ({}) - Commit: {}, Built: {}, HOB List: {:p}",
+            env!("CARGO_PKG_VERSION"),
+            self.platform_name,
+            option_env!("GIT_COMMIT").unwrap_or("unknown"),
+            option_env!("BUILD_DATE").unwrap_or("unknown"),
+            self.physical_hob_list
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+    use alloc::format;
+
+    use super::*;
+
+    #[test]
+    fn banner_reports_platform_version_and_hob_list() {
+        let banner = format!("{}", StartupBanner::new("QEMU Q35", 0x7F00_0000 as *const c_void));
+
+        assert!(banner.starts_with(&format!("DXE Core Platform Binary v{} (QEMU Q35)", env!("CARGO_PKG_VERSION"))));
+        assert!(banner.contains(&format!("Commit: {}", option_env!("GIT_COMMIT").unwrap_or("unknown"))));
+        assert!(banner.contains(&format!("Built: {}", option_env!("BUILD_DATE").unwrap_or("unknown"))));
+        assert!(banner.ends_with("HOB List: 0x7f000000"));
+    }
+}
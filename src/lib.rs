@@ -13,5 +13,6 @@
 
 #[cfg(any(feature = "aarch64", test))]
 pub mod armvirt;
+pub mod banner;
 #[cfg(any(feature = "x64", test))]
 pub mod q35;
@@ -9,5 +9,7 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 pub mod component;
+pub mod cpuid_to_smbios;
+pub mod fw_cfg;
 pub mod registers;
 pub mod timer;
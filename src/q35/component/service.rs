@@ -9,12 +9,42 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 #[coverage(off)]
+pub mod board_variant;
+#[coverage(off)]
+pub mod fw_cfg_test;
+#[cfg(any(feature = "iommu_dma_protection", test))]
+#[coverage(off)]
+pub mod iommu;
+#[cfg(any(feature = "ipmi_kcs", test))]
+#[coverage(off)]
+pub mod ipmi;
+#[coverage(off)]
 pub mod mm_config_provider;
 #[coverage(off)]
 pub mod mm_control;
 #[coverage(off)]
+pub mod mm_control_test;
+#[coverage(off)]
 pub mod mm_test;
 #[coverage(off)]
+pub mod pcie_test;
+#[coverage(off)]
+pub mod platform_reset;
+#[coverage(off)]
+pub mod power_button;
+#[cfg(any(feature = "qmp_client", test))]
+#[coverage(off)]
+pub mod qmp_client;
+#[cfg(any(feature = "s3_support", test))]
+#[coverage(off)]
+pub mod s3_resume;
+#[coverage(off)]
 pub mod smbios_platform;
 #[coverage(off)]
 pub mod smbios_test;
+#[coverage(off)]
+pub mod smm_verify;
+#[coverage(off)]
+pub mod timer_frequency;
+#[coverage(off)]
+pub mod timer_frequency_test;
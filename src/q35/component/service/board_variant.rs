@@ -0,0 +1,153 @@
+//! QEMU Q35 Board Variant Selector
+//!
+//! Selects board revision specific configuration for Q35-based platforms. The board revision is reported by the
+//! pre-DXE phase in the Board Revision HOB. If no HOB is present, the configuration of the stock QEMU Q35 machine is
+//! used.
+//!
+//! The selected ACPI (PMBASE) I/O port is checked by the MM configuration provider. PMBASE is not reprogrammed in DXE
+//! because the FADT, the DSDT and the ACPI PM timer port are derived from the value programmed by the pre-DXE phase.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina::component::{
+    component,
+    hob::{FromHob, Hob},
+    params::ConfigMut,
+};
+
+/// ACPI (PMBASE) I/O port programmed by the pre-DXE phase on the stock QEMU Q35 machine.
+pub const Q35_DEFAULT_PM_BASE: u16 = 0x600;
+
+/// ACPI (PMBASE) I/O port used by board revision 2 and later.
+pub const REVISION_2_PM_BASE: u16 = 0x400;
+
+/// ACPI (PMBASE) I/O port used by board revisions prior to 2.
+pub const REVISION_1_PM_BASE: u16 = 0x500;
+
+/// Reports the revision of the Q35-based board.
+#[derive(FromHob, Default, Clone, Copy, zerocopy::FromBytes)]
+#[hob = "b89e9701-3b04-4cf5-b13b-5b110b54f2de"]
+#[repr(C)]
+pub struct BoardRevisionHob {
+    revision: u32,
+}
+
+/// Board revision specific configuration.
+///
+/// Locked by [`QemuQ35BoardVariantSelector`] so that components depending on it are dispatched after the board
+/// variant is known.
+#[derive(Debug, Clone, Copy)]
+pub struct BoardVariantConfig {
+    /// The board revision, or `None` for the stock QEMU Q35 machine.
+    pub revision: Option<u32>,
+    /// The ACPI (PMBASE) I/O port used by this board.
+    pub pm_base: u16,
+}
+
+impl Default for BoardVariantConfig {
+    fn default() -> Self {
+        Self { revision: None, pm_base: Q35_DEFAULT_PM_BASE }
+    }
+}
+
+impl BoardVariantConfig {
+    /// Returns the configuration for board revision `revision`.
+    pub fn for_revision(revision: u32) -> Self {
+        let pm_base = if revision >= 2 { REVISION_2_PM_BASE } else { REVISION_1_PM_BASE };
+        Self { revision: Some(revision), pm_base }
+    }
+
+    /// Returns the ACPI (PMBASE) I/O port expected given the value `programmed` by the pre-DXE phase.
+    ///
+    /// Boards that report a revision expect the PMBASE of that revision. The stock QEMU Q35 machine expects the PMBASE
+    /// programmed by the pre-DXE phase.
+    pub fn expected_pm_base(&self, programmed: u16) -> u16 {
+        match self.revision {
+            Some(_) => self.pm_base,
+            None => programmed,
+        }
+    }
+}
+
+impl From<&BoardRevisionHob> for BoardVariantConfig {
+    fn from(hob: &BoardRevisionHob) -> Self {
+        Self::for_revision(hob.revision)
+    }
+}
+
+/// Populates the [`BoardVariantConfig`] from the Board Revision HOB.
+#[derive(Default)]
+pub struct QemuQ35BoardVariantSelector;
+
+#[component]
+impl QemuQ35BoardVariantSelector {
+    /// Creates a new instance of the QEMU Q35 board variant selector component.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Entry point for the QEMU Q35 board variant selector component.
+    ///
+    /// Always locks the `BoardVariantConfig`, falling back to the stock QEMU Q35 configuration if no Board Revision
+    /// HOB is present.
+    fn entry_point(
+        self,
+        board_revision_hob: Option<Hob<BoardRevisionHob>>,
+        mut config_mut: ConfigMut<BoardVariantConfig>,
+    ) -> patina::error::Result<()> {
+        log::debug!("Board Variant Selector Entry Point");
+
+        if let Some(hob) = board_revision_hob {
+            *config_mut = BoardVariantConfig::from(&*hob);
+        }
+
+        log::info!("Board Variant: {:?}", *config_mut);
+
+        config_mut.lock();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_from_hob(revision: u32) -> BoardVariantConfig {
+        BoardVariantConfig::from(&BoardRevisionHob::parse(&revision.to_le_bytes()))
+    }
+
+    #[test]
+    fn revision_1_hob_selects_revision_1_pm_base() {
+        let config = config_from_hob(1);
+        assert_eq!(config.revision, Some(1));
+        assert_eq!(config.pm_base, REVISION_1_PM_BASE);
+        assert_eq!(config.expected_pm_base(Q35_DEFAULT_PM_BASE), REVISION_1_PM_BASE);
+    }
+
+    #[test]
+    fn revision_2_hob_selects_revision_2_pm_base() {
+        let config = config_from_hob(2);
+        assert_eq!(config.revision, Some(2));
+        assert_eq!(config.pm_base, REVISION_2_PM_BASE);
+        assert_eq!(config.expected_pm_base(Q35_DEFAULT_PM_BASE), REVISION_2_PM_BASE);
+    }
+
+    #[test]
+    fn later_revisions_use_revision_2_pm_base() {
+        assert_eq!(config_from_hob(7).pm_base, REVISION_2_PM_BASE);
+    }
+
+    #[test]
+    fn stock_machine_keeps_programmed_pm_base() {
+        let config = BoardVariantConfig::default();
+        assert_eq!(config.revision, None);
+        assert_eq!(config.expected_pm_base(Q35_DEFAULT_PM_BASE), Q35_DEFAULT_PM_BASE);
+        assert_eq!(config.expected_pm_base(0xB000), 0xB000);
+    }
+}
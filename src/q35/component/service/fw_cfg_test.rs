@@ -0,0 +1,31 @@
+//! QEMU Q35 fw_cfg Test
+//!
+//! Verifies that the QEMU fw_cfg device is accessible on the QEMU Q35 platform.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+
+use patina_test::{patina_test, u_assert};
+
+use crate::q35::fw_cfg;
+
+/// Smallest amount of RAM the QEMU Q35 platform is expected to be launched with.
+const MIN_RAM_SIZE: u64 = 128 * 1024 * 1024;
+
+/// Tests that the guest RAM size reported by fw_cfg is available and plausible.
+#[patina_test]
+fn q35_fw_cfg_ram_size_test() -> patina_test::error::Result {
+    let Some(ram_size) = fw_cfg::ram_size() else {
+        return Err("fw_cfg device not present");
+    };
+    log::debug!("fw_cfg RAM size: {ram_size:#X}");
+
+    u_assert!(ram_size >= MIN_RAM_SIZE, "fw_cfg RAM size is less than 128 MB");
+
+    Ok(())
+}
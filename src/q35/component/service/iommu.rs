@@ -0,0 +1,185 @@
+//! QEMU Q35 Intel VT-d IOMMU Support
+//!
+//! Enables DMA remapping on the Intel VT-d IOMMU emulated by QEMU (`-device intel-iommu`) with an empty root table so
+//! that all device DMA is blocked until an OS or IOMMU driver takes ownership of the remapping hardware.
+//!
+//! Device drivers that perform DMA during boot will fault while translation is enabled, so this component is only
+//! built with the `iommu_dma_protection` feature. The feature should only be enabled when QEMU is launched with
+//! `-device intel-iommu` so that the remapping hardware register range is present.
+//!
+//! ## References
+//!
+//! - [Intel Virtualization Technology for Directed I/O Architecture Specification](https://www.intel.com/content/www/us/en/content-details/774206/intel-virtualization-technology-for-directed-i-o-architecture-specification.html)
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
+
+use patina::{
+    boot_services::{BootServices, StandardBootServices, allocation::AllocType},
+    component::component,
+    efi_types::EfiMemoryType,
+    error::{EfiError, Result},
+};
+
+/// MMIO base address of the QEMU Q35 VT-d remapping hardware unit.
+pub const IOMMU_BASE: u64 = 0xFED9_0000;
+/// Version register offset.
+pub const IOMMU_VER_REG: u32 = 0x000;
+/// Capability register offset.
+pub const IOMMU_CAP_REG: u32 = 0x008;
+/// Global command register offset.
+pub const IOMMU_GCMD_REG: u32 = 0x018;
+/// Global status register offset.
+pub const IOMMU_GSTS_REG: u32 = 0x01C;
+/// Root table address register offset.
+pub const IOMMU_RTADDR_REG: u32 = 0x020;
+
+/// Supported Adjusted Guest Address Widths capability field.
+const CAP_SAGAW_MASK: u64 = 0x1F << 8;
+/// Protected Low-Memory Region capability bit.
+const CAP_PLMR: u64 = 1 << 5;
+/// Protected High-Memory Region capability bit.
+const CAP_PHMR: u64 = 1 << 6;
+
+/// Translation Enable command and status bit.
+const GCMD_TE: u32 = 1 << 31;
+/// Set Root Table Pointer command and status bit.
+const GCMD_SRTP: u32 = 1 << 30;
+/// Global status bits that reflect persistent state and may be written back to the global command register. The
+/// remaining bits are one-shot commands that must not be re-issued.
+const GSTS_PERSISTENT_MASK: u32 = 0x96FF_FFFF;
+
+/// If the remapping hardware stops responding, avoid hanging forever.
+const MAX_WAIT_CYCLES: usize = 1_000_000;
+
+/// The QEMU Q35 VT-d IOMMU component.
+#[derive(Default)]
+pub struct QemuQ35Iommu;
+
+#[component]
+impl QemuQ35Iommu {
+    /// Creates a new instance of the QEMU Q35 IOMMU component.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Entry point for the QEMU Q35 IOMMU component.
+    ///
+    /// Does nothing if no remapping hardware unit is present.
+    fn entry_point(self, boot_services: StandardBootServices) -> Result<()> {
+        log::debug!("IOMMU Entry Point");
+
+        let version = read_reg32(IOMMU_VER_REG);
+        if version == 0 || version == u32::MAX {
+            log::info!("No VT-d remapping hardware found at {IOMMU_BASE:#X}");
+            return Ok(());
+        }
+
+        let capabilities = read_reg64(IOMMU_CAP_REG);
+        log::info!("VT-d Version: {}.{}, Capabilities: {capabilities:#X}", (version >> 4) & 0xF, version & 0xF);
+
+        if capabilities & CAP_SAGAW_MASK == 0 {
+            log::error!("VT-d remapping hardware reports no supported address widths");
+            return Err(EfiError::Unsupported);
+        }
+        if capabilities & (CAP_PLMR | CAP_PHMR) != CAP_PLMR | CAP_PHMR {
+            log::warn!("VT-d remapping hardware does not support protected memory regions");
+        }
+
+        // An all-zero root table marks every bus as not present which blocks all DMA once translation is enabled. The
+        // hardware keeps using the table after ExitBootServices, so it must not be reclaimed by the OS.
+        let root_table = boot_services
+            .allocate_pages(AllocType::AnyPage, EfiMemoryType::ReservedMemoryType, 1)
+            .map_err(EfiError::from)?;
+        // SAFETY: The page was just allocated and is exclusively owned by this component.
+        unsafe { core::ptr::write_bytes(root_table as *mut u8, 0, patina::uefi_pages_to_size!(1)) };
+
+        write_reg64(IOMMU_RTADDR_REG, root_table as u64);
+        global_command(GCMD_SRTP)?;
+        global_command(GCMD_TE)?;
+
+        log::info!("VT-d DMA remapping enabled with root table at {root_table:#X}");
+
+        Ok(())
+    }
+}
+
+/// Issues the one-shot or persistent command `command` and waits for the hardware to report it in the global status.
+fn global_command(command: u32) -> Result<()> {
+    write_reg32(IOMMU_GCMD_REG, global_command_value(read_reg32(IOMMU_GSTS_REG), command));
+
+    for _ in 0..MAX_WAIT_CYCLES {
+        if read_reg32(IOMMU_GSTS_REG) & command != 0 {
+            return Ok(());
+        }
+    }
+
+    log::error!("VT-d timeout waiting for global command {command:#X}");
+    Err(EfiError::Timeout)
+}
+
+/// Returns the global command register value that issues `command` while preserving the persistent state in the
+/// global status `status`.
+fn global_command_value(status: u32, command: u32) -> u32 {
+    (status & GSTS_PERSISTENT_MASK) | command
+}
+
+fn read_reg32(offset: u32) -> u32 {
+    // SAFETY: The VT-d register set is mapped at a fixed MMIO address on QEMU Q35.
+    unsafe { core::ptr::read_volatile((IOMMU_BASE + offset as u64) as *const u32) }
+}
+
+fn write_reg32(offset: u32, value: u32) {
+    // SAFETY: The VT-d register set is mapped at a fixed MMIO address on QEMU Q35.
+    unsafe { core::ptr::write_volatile((IOMMU_BASE + offset as u64) as *mut u32, value) }
+}
+
+fn read_reg64(offset: u32) -> u64 {
+    // SAFETY: The VT-d register set is mapped at a fixed MMIO address on QEMU Q35.
+    unsafe { core::ptr::read_volatile((IOMMU_BASE + offset as u64) as *const u64) }
+}
+
+fn write_reg64(offset: u32, value: u64) {
+    // SAFETY: The VT-d register set is mapped at a fixed MMIO address on QEMU Q35.
+    unsafe { core::ptr::write_volatile((IOMMU_BASE + offset as u64) as *mut u64, value) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_command_does_not_reissue_root_table_pointer() {
+        // Root table pointer already set: enabling translation must not latch the root table pointer again.
+        assert_eq!(global_command_value(GCMD_SRTP, GCMD_TE), GCMD_TE);
+    }
+
+    #[test]
+    fn global_command_preserves_translation_enable() {
+        assert_eq!(global_command_value(GCMD_TE, GCMD_SRTP), GCMD_TE | GCMD_SRTP);
+    }
+
+    #[test]
+    fn global_command_drops_one_shot_status_bits() {
+        // Write buffer flush (bit 27) and interrupt remapping table pointer (bit 24) status are one-shot commands.
+        let one_shot = (1 << 27) | (1 << 24);
+        assert_eq!(global_command_value(one_shot | GCMD_TE, GCMD_SRTP), GCMD_TE | GCMD_SRTP);
+    }
+
+    #[test]
+    fn wide_registers_are_naturally_aligned() {
+        assert!((IOMMU_BASE + IOMMU_CAP_REG as u64).is_multiple_of(8));
+        assert!((IOMMU_BASE + IOMMU_RTADDR_REG as u64).is_multiple_of(8));
+    }
+
+    #[test]
+    fn persistent_status_mask_excludes_one_shot_commands() {
+        assert_eq!(GSTS_PERSISTENT_MASK & GCMD_SRTP, 0);
+        assert_ne!(GSTS_PERSISTENT_MASK & GCMD_TE, 0);
+    }
+}
@@ -0,0 +1,502 @@
+//! QEMU Q35 IPMI Keyboard Controller Style (KCS) Interface
+//!
+//! Provides access to the BMC emulated by QEMU (`-device ipmi-bmc-sim` with `-device isa-ipmi-kcs`) over the IPMI
+//! KCS system interface. The BMC exposes mock temperature and fan speed sensors that can be queried by sensor number.
+//!
+//! The component is only built with the `ipmi_kcs` feature, which should be enabled when QEMU is launched with a BMC.
+//!
+//! ## References
+//!
+//! - [IPMI Specification v2.0](https://www.intel.com/content/dam/www/public/us/en/documents/specification-updates/ipmi-intelligent-platform-mgt-interface-spec-2nd-gen-v2-0-spec-update.pdf)
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use patina::{
+    component::{Storage, component, service::IntoService},
+    error::{EfiError, Result},
+};
+use x86_64::instructions::port::Port;
+
+/// KCS data register I/O port (DATA_IN on write, DATA_OUT on read).
+pub const IPMI_KCS_DATA: u16 = 0xCA2;
+/// KCS status (read) and command (write) register I/O port.
+pub const IPMI_KCS_STATUS: u16 = 0xCA3;
+
+/// Output Buffer Full status bit.
+const KCS_STATUS_OBF: u8 = 0x01;
+/// Input Buffer Full status bit.
+const KCS_STATUS_IBF: u8 = 0x02;
+/// Interface state field mask.
+const KCS_STATUS_STATE_MASK: u8 = 0xC0;
+/// Idle interface state.
+const KCS_STATE_IDLE: u8 = 0x00;
+/// Read interface state.
+const KCS_STATE_READ: u8 = 0x40;
+/// Write interface state.
+const KCS_STATE_WRITE: u8 = 0x80;
+
+/// Control code that starts a write transfer.
+const KCS_CMD_WRITE_START: u8 = 0x61;
+/// Control code that precedes the final byte of a write transfer.
+const KCS_CMD_WRITE_END: u8 = 0x62;
+/// Control code that acknowledges a byte of a read transfer.
+const KCS_CMD_READ: u8 = 0x68;
+
+/// Application network function.
+const NETFN_APP: u8 = 0x06;
+/// Sensor/Event network function.
+const NETFN_SENSOR_EVENT: u8 = 0x04;
+/// Get Device ID command (Application).
+const CMD_GET_DEVICE_ID: u8 = 0x01;
+/// Get Sensor Reading command (Sensor/Event).
+const CMD_GET_SENSOR_READING: u8 = 0x2D;
+
+/// Sensor reading flag indicating the reading is not available.
+const SENSOR_READING_UNAVAILABLE: u8 = 0x20;
+
+/// If the BMC stops responding, avoid hanging forever.
+const MAX_WAIT_CYCLES: usize = 1_000_000;
+
+/// Maximum response size accepted from the BMC.
+const MAX_RESPONSE_SIZE: usize = 64;
+
+/// Device information returned by the IPMI Get Device ID command.
+#[derive(Debug, Clone, Copy)]
+pub struct IpmiDeviceId {
+    /// Device ID.
+    pub device_id: u8,
+    /// Device revision.
+    pub device_revision: u8,
+    /// Major firmware revision.
+    pub firmware_major: u8,
+    /// Minor firmware revision (BCD encoded).
+    pub firmware_minor: u8,
+    /// IPMI version (BCD encoded, e.g. `0x20` for IPMI 2.0).
+    pub ipmi_version: u8,
+    /// IANA manufacturer ID.
+    pub manufacturer_id: u32,
+    /// Product ID.
+    pub product_id: u16,
+}
+
+/// Access to the KCS interface registers, abstracted so the KCS transfer state machine can be tested without a BMC.
+trait KcsRegisters {
+    /// Reads the status register.
+    fn read_status(&self) -> u8;
+    /// Writes `command` to the command register.
+    fn write_command(&self, command: u8);
+    /// Reads the data register (DATA_OUT).
+    fn read_data(&self) -> u8;
+    /// Writes `data` to the data register (DATA_IN).
+    fn write_data(&self, data: u8);
+}
+
+/// The KCS interface registers at the fixed QEMU Q35 I/O ports.
+#[derive(Default)]
+struct KcsIoPorts;
+
+impl KcsRegisters for KcsIoPorts {
+    fn read_status(&self) -> u8 {
+        // SAFETY: The KCS status register is a fixed I/O port on QEMU Q35 and reading it has no side effects.
+        unsafe { Port::<u8>::new(IPMI_KCS_STATUS).read() }
+    }
+
+    fn write_command(&self, command: u8) {
+        // SAFETY: The KCS command register is a fixed I/O port on QEMU Q35.
+        unsafe { Port::<u8>::new(IPMI_KCS_STATUS).write(command) }
+    }
+
+    fn read_data(&self) -> u8 {
+        // SAFETY: The KCS data register is a fixed I/O port on QEMU Q35.
+        unsafe { Port::<u8>::new(IPMI_KCS_DATA).read() }
+    }
+
+    fn write_data(&self, data: u8) {
+        // SAFETY: The KCS data register is a fixed I/O port on QEMU Q35.
+        unsafe { Port::<u8>::new(IPMI_KCS_DATA).write(data) }
+    }
+}
+
+/// The QEMU Q35 IPMI KCS component.
+///
+/// Installs itself as a service if a BMC responds on the KCS interface so that other components can query the BMC
+/// without knowledge of the transport.
+#[derive(IntoService, Default)]
+#[service(QemuQ35IpmiKcs)]
+pub struct QemuQ35IpmiKcs {
+    kcs: Kcs<KcsIoPorts>,
+}
+
+#[component]
+impl QemuQ35IpmiKcs {
+    /// Creates a new instance of the QEMU Q35 IPMI KCS component.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Entry point for the QEMU Q35 IPMI KCS component.
+    ///
+    /// The service is only installed if a BMC is present and answers the Get Device ID command.
+    fn entry_point(self, storage: &mut Storage) -> Result<()> {
+        log::debug!("IPMI KCS Entry Point");
+
+        if self.kcs.registers.read_status() == 0xFF {
+            log::info!("No IPMI KCS interface found at {IPMI_KCS_DATA:#X}");
+            return Ok(());
+        }
+
+        let device_id = self.get_device_id()?;
+        log::info!(
+            "IPMI BMC - Device ID: {:#X}, Firmware: {}.{:02X}, IPMI Version: {:#X}, Manufacturer: {:#X}, Product: {:#X}",
+            device_id.device_id,
+            device_id.firmware_major,
+            device_id.firmware_minor,
+            device_id.ipmi_version,
+            device_id.manufacturer_id,
+            device_id.product_id
+        );
+
+        storage.add_service(self);
+
+        Ok(())
+    }
+
+    /// Issues the IPMI Get Device ID command.
+    pub fn get_device_id(&self) -> Result<IpmiDeviceId> {
+        self.kcs.get_device_id()
+    }
+
+    /// Issues the IPMI Get Sensor Reading command for `sensor_number`.
+    ///
+    /// Returns `None` if the BMC does not respond, reports an error, or flags the reading as unavailable.
+    pub fn get_sensor_reading(&self, sensor_number: u8) -> Option<u8> {
+        self.kcs.get_sensor_reading(sensor_number)
+    }
+}
+
+/// The IPMI KCS system interface over `registers`.
+#[derive(Default)]
+struct Kcs<R: KcsRegisters> {
+    registers: R,
+}
+
+impl<R: KcsRegisters> Kcs<R> {
+    /// Issues the IPMI Get Device ID command.
+    fn get_device_id(&self) -> Result<IpmiDeviceId> {
+        let response = self.send_command(NETFN_APP, CMD_GET_DEVICE_ID, &[])?;
+        if response.len() < 11 {
+            log::error!("IPMI Get Device ID response too short: {} bytes", response.len());
+            return Err(EfiError::DeviceError);
+        }
+
+        Ok(IpmiDeviceId {
+            device_id: response[0],
+            device_revision: response[1] & 0x0F,
+            firmware_major: response[2] & 0x7F,
+            firmware_minor: response[3],
+            ipmi_version: response[4],
+            manufacturer_id: u32::from_le_bytes([response[6], response[7], response[8], 0]),
+            product_id: u16::from_le_bytes([response[9], response[10]]),
+        })
+    }
+
+    /// Issues the IPMI Get Sensor Reading command for `sensor_number`.
+    ///
+    /// Returns `None` if the BMC does not respond, reports an error, or flags the reading as unavailable.
+    fn get_sensor_reading(&self, sensor_number: u8) -> Option<u8> {
+        let response = self.send_command(NETFN_SENSOR_EVENT, CMD_GET_SENSOR_READING, &[sensor_number]).ok()?;
+        match response.as_slice() {
+            [reading, flags, ..] if flags & SENSOR_READING_UNAVAILABLE == 0 => Some(*reading),
+            _ => None,
+        }
+    }
+
+    /// Sends a request to the BMC and returns the response data following the completion code.
+    fn send_command(&self, netfn: u8, command: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let mut request = Vec::with_capacity(data.len() + 2);
+        request.push(netfn << 2);
+        request.push(command);
+        request.extend_from_slice(data);
+
+        self.write_request(&request)?;
+        let response = self.read_response()?;
+
+        // Response format: NetFn/LUN, Command, Completion Code, Data...
+        match response.as_slice() {
+            [_, _, 0x00, data @ ..] => Ok(data.to_vec()),
+            [_, _, completion_code, ..] => {
+                log::warn!("IPMI command {netfn:#X}:{command:#X} failed with completion code {completion_code:#X}");
+                Err(EfiError::DeviceError)
+            }
+            _ => {
+                log::error!("IPMI command {netfn:#X}:{command:#X} returned a truncated response");
+                Err(EfiError::DeviceError)
+            }
+        }
+    }
+
+    /// Performs the KCS write transfer phase for `request`.
+    fn write_request(&self, request: &[u8]) -> Result<()> {
+        let Some((last, body)) = request.split_last() else {
+            return Err(EfiError::InvalidParameter);
+        };
+
+        self.wait_ibf_clear()?;
+        self.registers.write_command(KCS_CMD_WRITE_START);
+        self.wait_ibf_clear()?;
+        self.expect_state(KCS_STATE_WRITE)?;
+        self.clear_obf();
+
+        for byte in body {
+            self.registers.write_data(*byte);
+            self.wait_ibf_clear()?;
+            self.expect_state(KCS_STATE_WRITE)?;
+            self.clear_obf();
+        }
+
+        self.registers.write_command(KCS_CMD_WRITE_END);
+        self.wait_ibf_clear()?;
+        self.expect_state(KCS_STATE_WRITE)?;
+        self.clear_obf();
+        self.registers.write_data(*last);
+
+        Ok(())
+    }
+
+    /// Performs the KCS read transfer phase and returns the raw response.
+    fn read_response(&self) -> Result<Vec<u8>> {
+        let mut response = Vec::new();
+        loop {
+            self.wait_ibf_clear()?;
+            match self.registers.read_status() & KCS_STATUS_STATE_MASK {
+                KCS_STATE_READ => {
+                    self.wait_obf_set()?;
+                    let byte = self.registers.read_data();
+                    if response.len() < MAX_RESPONSE_SIZE {
+                        response.push(byte);
+                    }
+                    self.registers.write_data(KCS_CMD_READ);
+                }
+                KCS_STATE_IDLE => {
+                    self.wait_obf_set()?;
+                    // Dummy byte that completes the transfer.
+                    self.registers.read_data();
+                    return Ok(response);
+                }
+                state => {
+                    log::error!("IPMI KCS unexpected state {state:#X} during read");
+                    return Err(EfiError::DeviceError);
+                }
+            }
+        }
+    }
+
+    fn wait_ibf_clear(&self) -> Result<()> {
+        for _ in 0..MAX_WAIT_CYCLES {
+            if self.registers.read_status() & KCS_STATUS_IBF == 0 {
+                return Ok(());
+            }
+        }
+        log::warn!("IPMI KCS timeout waiting for IBF to clear");
+        Err(EfiError::Timeout)
+    }
+
+    fn wait_obf_set(&self) -> Result<()> {
+        for _ in 0..MAX_WAIT_CYCLES {
+            if self.registers.read_status() & KCS_STATUS_OBF != 0 {
+                return Ok(());
+            }
+        }
+        log::warn!("IPMI KCS timeout waiting for OBF to set");
+        Err(EfiError::Timeout)
+    }
+
+    fn expect_state(&self, state: u8) -> Result<()> {
+        let status = self.registers.read_status();
+        if status & KCS_STATUS_STATE_MASK != state {
+            log::error!("IPMI KCS unexpected status {status:#X}, expected state {state:#X}");
+            return Err(EfiError::DeviceError);
+        }
+        Ok(())
+    }
+
+    fn clear_obf(&self) {
+        if self.registers.read_status() & KCS_STATUS_OBF != 0 {
+            self.registers.read_data();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+    use core::cell::{Cell, RefCell};
+
+    /// A simulated BMC that implements the BMC side of the KCS transfer state machine.
+    ///
+    /// Requests are answered instantly, so IBF never stays set unless `stuck_ibf` is set.
+    #[derive(Default)]
+    struct MockBmc {
+        state: Cell<u8>,
+        obf: Cell<bool>,
+        data_out: Cell<u8>,
+        write_end: Cell<bool>,
+        stuck_ibf: bool,
+        /// Status reported instead of the state machine status when set.
+        status_override: Option<u8>,
+        /// Completion code and data returned for every request.
+        completion_code: u8,
+        response_data: Vec<u8>,
+        request: RefCell<Vec<u8>>,
+        pending: RefCell<VecDeque<u8>>,
+    }
+
+    impl MockBmc {
+        fn responding(completion_code: u8, response_data: &[u8]) -> Kcs<Self> {
+            Kcs { registers: Self { completion_code, response_data: response_data.to_vec(), ..Default::default() } }
+        }
+
+        fn output(&self, data: u8) {
+            self.data_out.set(data);
+            self.obf.set(true);
+        }
+    }
+
+    impl KcsRegisters for MockBmc {
+        fn read_status(&self) -> u8 {
+            if let Some(status) = self.status_override {
+                return status;
+            }
+            let ibf = if self.stuck_ibf { KCS_STATUS_IBF } else { 0 };
+            let obf = if self.obf.get() { KCS_STATUS_OBF } else { 0 };
+            self.state.get() | ibf | obf
+        }
+
+        fn write_command(&self, command: u8) {
+            match command {
+                KCS_CMD_WRITE_START => {
+                    self.state.set(KCS_STATE_WRITE);
+                    self.request.borrow_mut().clear();
+                    // The BMC places a dummy byte in DATA_OUT that the host clears.
+                    self.output(0);
+                }
+                KCS_CMD_WRITE_END => self.write_end.set(true),
+                _ => panic!("unexpected KCS command {command:#X}"),
+            }
+        }
+
+        fn read_data(&self) -> u8 {
+            self.obf.set(false);
+            self.data_out.get()
+        }
+
+        fn write_data(&self, data: u8) {
+            match self.state.get() {
+                KCS_STATE_WRITE => {
+                    self.request.borrow_mut().push(data);
+                    if self.write_end.replace(false) {
+                        let request = self.request.borrow();
+                        let mut pending = self.pending.borrow_mut();
+                        pending.extend([request[0] + (1 << 2), request[1], self.completion_code]);
+                        pending.extend(self.response_data.iter().copied());
+                        self.state.set(KCS_STATE_READ);
+                        self.output(pending.pop_front().unwrap());
+                    }
+                }
+                KCS_STATE_READ => {
+                    assert_eq!(data, KCS_CMD_READ, "read transfer byte was not acknowledged with READ");
+                    match self.pending.borrow_mut().pop_front() {
+                        Some(byte) => self.output(byte),
+                        None => {
+                            self.state.set(KCS_STATE_IDLE);
+                            self.output(0);
+                        }
+                    }
+                }
+                state => panic!("data written in KCS state {state:#X}"),
+            }
+        }
+    }
+
+    /// The Get Device ID response data reported by the QEMU `ipmi-bmc-sim` device.
+    const QEMU_DEVICE_ID: [u8; 11] = [0x20, 0x01, 0x02, 0x05, 0x02, 0xBF, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+    #[test]
+    fn get_device_id_parses_response() {
+        let kcs = MockBmc::responding(0, &QEMU_DEVICE_ID);
+        let device_id = kcs.get_device_id().unwrap();
+
+        assert_eq!(*kcs.registers.request.borrow(), [NETFN_APP << 2, CMD_GET_DEVICE_ID]);
+        assert_eq!(device_id.device_id, 0x20);
+        assert_eq!(device_id.device_revision, 0x01);
+        assert_eq!(device_id.firmware_major, 0x02);
+        assert_eq!(device_id.firmware_minor, 0x05);
+        assert_eq!(device_id.ipmi_version, 0x02);
+        assert_eq!(kcs.registers.state.get(), KCS_STATE_IDLE);
+        assert!(!kcs.registers.obf.get());
+    }
+
+    #[test]
+    fn get_device_id_rejects_short_response() {
+        let kcs = MockBmc::responding(0, &QEMU_DEVICE_ID[..10]);
+        assert_eq!(kcs.get_device_id().unwrap_err(), EfiError::DeviceError);
+    }
+
+    #[test]
+    fn get_sensor_reading_sends_sensor_number() {
+        let kcs = MockBmc::responding(0, &[0x2A, 0x40, 0x00]);
+        assert_eq!(kcs.get_sensor_reading(0x05), Some(0x2A));
+        assert_eq!(*kcs.registers.request.borrow(), [NETFN_SENSOR_EVENT << 2, CMD_GET_SENSOR_READING, 0x05]);
+    }
+
+    #[test]
+    fn unavailable_sensor_reading_is_none() {
+        let kcs = MockBmc::responding(0, &[0x2A, SENSOR_READING_UNAVAILABLE]);
+        assert_eq!(kcs.get_sensor_reading(0x05), None);
+    }
+
+    #[test]
+    fn completion_code_error_is_reported() {
+        // 0xCB: Requested sensor, data, or record not present.
+        let kcs = MockBmc::responding(0xCB, &[]);
+        assert_eq!(kcs.send_command(NETFN_SENSOR_EVENT, CMD_GET_SENSOR_READING, &[0x7F]), Err(EfiError::DeviceError));
+        assert_eq!(kcs.get_sensor_reading(0x7F), None);
+    }
+
+    #[test]
+    fn long_response_is_truncated() {
+        let kcs = MockBmc::responding(0, &[0x55; MAX_RESPONSE_SIZE * 2]);
+        let response = kcs.send_command(NETFN_APP, CMD_GET_DEVICE_ID, &[]).unwrap();
+        // The header and completion code count towards the maximum response size.
+        assert_eq!(response.len(), MAX_RESPONSE_SIZE - 3);
+        assert_eq!(kcs.registers.state.get(), KCS_STATE_IDLE);
+    }
+
+    #[test]
+    fn error_state_fails_transfer() {
+        let kcs = Kcs { registers: MockBmc { status_override: Some(0xC0), ..Default::default() } };
+        assert_eq!(kcs.send_command(NETFN_APP, CMD_GET_DEVICE_ID, &[]), Err(EfiError::DeviceError));
+    }
+
+    #[test]
+    fn stuck_input_buffer_times_out() {
+        let kcs = Kcs { registers: MockBmc { stuck_ibf: true, ..Default::default() } };
+        assert_eq!(kcs.send_command(NETFN_APP, CMD_GET_DEVICE_ID, &[]), Err(EfiError::Timeout));
+    }
+
+    #[test]
+    fn empty_request_is_rejected() {
+        let kcs = MockBmc::responding(0, &[]);
+        assert_eq!(kcs.write_request(&[]), Err(EfiError::InvalidParameter));
+    }
+}
@@ -9,14 +9,20 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 
-use patina::component::{
-    component,
-    hob::{FromHob, Hob},
-    params::ConfigMut,
+use patina::{
+    base::UEFI_PAGE_SIZE,
+    component::{
+        component,
+        hob::{FromHob, Hob},
+        params::{Config, ConfigMut},
+    },
+    error::EfiError,
 };
-use patina_mm::config::{CommunicateBuffer, MmCommunicationConfiguration};
+use patina_mm::config::{AcpiBase, CommunicateBuffer, MmCommunicationConfiguration};
 
-use crate::q35::registers as register;
+use core::ops::Range;
+
+use crate::q35::{component::service::board_variant::BoardVariantConfig, registers as register};
 
 extern crate alloc;
 
@@ -41,6 +47,81 @@ pub struct MmCommRegionHob {
     pages: u64,
 }
 
+/// The type of a MM Communication Region as reported in the [`MmCommRegionHob`].
+///
+/// The value is used as the communicate buffer ID so that MM consumers can select the region they communicate through.
+/// Types not known to this component are passed through unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmBufferType {
+    /// Region used for general MM communication.
+    General,
+    /// Region used as a ring buffer, e.g. for MM logging.
+    RingBuffer,
+    /// Region used to communicate with the MM Supervisor.
+    MmSupervisor,
+    /// Region of a type not known to this component.
+    Other(u8),
+}
+
+impl From<u8> for MmBufferType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::General,
+            1 => Self::RingBuffer,
+            2 => Self::MmSupervisor,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<MmBufferType> for u8 {
+    fn from(buffer_type: MmBufferType) -> Self {
+        match buffer_type {
+            MmBufferType::General => 0,
+            MmBufferType::RingBuffer => 1,
+            MmBufferType::MmSupervisor => 2,
+            MmBufferType::Other(value) => value,
+        }
+    }
+}
+
+impl TryFrom<u64> for MmBufferType {
+    type Error = u64;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        u8::try_from(value).map(Self::from).map_err(|_| value)
+    }
+}
+
+impl MmCommRegionHob {
+    /// Returns the buffer type of this region, or `None` if the HOB reports a buffer type that does not fit in a
+    /// communicate buffer ID.
+    pub fn buffer_type_enum(&self) -> Option<MmBufferType> {
+        MmBufferType::try_from(self.buffer_type).ok()
+    }
+
+    /// Returns the size in bytes of the region.
+    pub fn size(&self) -> usize {
+        patina::uefi_pages_to_size!(self.pages as usize)
+    }
+
+    /// Returns the address range `[address, address + size)` of the region.
+    pub fn range(&self) -> Range<usize> {
+        let start = self.address as usize;
+        start..start.saturating_add(self.size())
+    }
+
+    /// Returns the total size in bytes of all regions in `iter`.
+    pub fn total_size(iter: impl Iterator<Item = MmCommRegionHob>) -> usize {
+        iter.map(|hob| hob.size()).sum()
+    }
+
+    /// Returns the size in bytes of the largest region in `iter`, or `None` if `iter` is empty.
+    pub fn largest_buffer(iter: impl Iterator<Item = MmCommRegionHob>) -> Option<usize> {
+        iter.map(|hob| hob.size()).max()
+    }
+}
+
 #[component]
 impl MmConfigurationProvider {
     /// Entry point for the MM Configuration Provider.
@@ -48,6 +129,9 @@ impl MmConfigurationProvider {
     /// Depends on at least one instance of the MM Communicate Region HOB to be present in the HOB list. This component
     /// will not be dispatched if no MM Communicate Region HOBs are present in the HOB list.
     ///
+    /// Depends on the `BoardVariantConfig` being locked by the board variant selector so the PMBASE used by the board
+    /// revision can be checked against the value programmed by the pre-DXE phase.
+    ///
     /// Depends on a mutable `patina::component::config::mm::MmCommunicationConfiguration` instance to be in storage. This
     /// component will populate the given configuration instance with runtime information about the MM configuration
     /// and lock the configuration to prevent further modifications and to allow components with immutable dependencies
@@ -56,6 +140,7 @@ impl MmConfigurationProvider {
     /// ## Parameters
     ///
     /// - `mm_comm_region_hob`: The MM Communicate Region HOB(s) to be used for MM communication.
+    /// - `board_variant`: The board revision specific configuration.
     /// - `config_mut`: A mutable reference to the MM Configuration Config instance to be populated with runtime
     ///   information.
     ///
@@ -67,6 +152,7 @@ impl MmConfigurationProvider {
     pub fn entry_point(
         self,
         mm_comm_region_hob: Hob<MmCommRegionHob>,
+        board_variant: Config<BoardVariantConfig>,
         mut config_mut: ConfigMut<MmCommunicationConfiguration>,
     ) -> patina::error::Result<()> {
         log::debug!("MM Configuration Provider Entry Point");
@@ -81,21 +167,50 @@ impl MmConfigurationProvider {
         log::info!("ACPI I/O Port Address: {:#X}", pm_base as usize);
         log::info!("ACPI (PMBASE) I/O Port: {pm_base_value:#X}");
 
+        // PMBASE is not reprogrammed here: the FADT, the DSDT and the ACPI PM timer port all use the programmed value.
+        let board_pm_base = board_variant.expected_pm_base(pm_base_value);
+        if pm_base_value != board_pm_base {
+            log::warn!(
+                "ACPI (PMBASE) I/O Port {pm_base_value:#X} differs from {board_pm_base:#X} expected for board revision {:?}",
+                board_variant.revision
+            );
+        }
+
+        validate_pmbase(pm_base_value)?;
+
         config_mut.acpi_base = pm_base_value.into();
 
-        log::info!("Found {} MM Communicate Region HOBs", mm_comm_region_hob.iter().count());
+        log::info!(
+            "Found {} MM Communicate Region HOBs ({:#X} bytes total, {:#X} bytes largest)",
+            mm_comm_region_hob.iter().count(),
+            MmCommRegionHob::total_size(mm_comm_region_hob.iter().copied()),
+            MmCommRegionHob::largest_buffer(mm_comm_region_hob.iter().copied()).unwrap_or(0)
+        );
+
+        let regions: alloc::vec::Vec<Range<usize>> = mm_comm_region_hob.iter().map(MmCommRegionHob::range).collect();
+        if let Some((first, second)) = find_overlap(&regions) {
+            log::error!(
+                "MM Communicate Region [{:#X}, {:#X}) overlaps MM Communicate Region [{:#X}, {:#X})",
+                regions[first].start,
+                regions[first].end,
+                regions[second].start,
+                regions[second].end
+            );
+            return Err(EfiError::InvalidParameter);
+        }
 
         for hob in mm_comm_region_hob.iter() {
             log::debug!("HOB Address: {:#X}", hob.address);
             log::debug!("HOB Pages: {:#X}", hob.pages);
             log::debug!("HOB Buffer Type: {:#X}", hob.buffer_type);
 
+            let Some(buffer_type) = hob.buffer_type_enum() else {
+                log::error!("Skipping MM Communicate Region HOB with invalid buffer type {:#X}", hob.buffer_type);
+                continue;
+            };
+
             let buffer = unsafe {
-                CommunicateBuffer::from_raw_parts(
-                    hob.address as usize as *mut u8,
-                    hob.pages as usize * patina::base::UEFI_PAGE_SIZE,
-                    hob.buffer_type as u8,
-                )
+                CommunicateBuffer::from_raw_parts(hob.address as usize as *mut u8, hob.size(), buffer_type.into())
             };
 
             match buffer {
@@ -109,8 +224,303 @@ impl MmConfigurationProvider {
             };
         }
 
+        validate(&config_mut)?;
+
         config_mut.lock();
 
         Ok(())
     }
 }
+
+/// Size of the ICH9 ACPI I/O register block decoded at PMBASE.
+const PMBASE_IO_SIZE: u32 = 0x80;
+
+/// Validates the ACPI (PMBASE) I/O port read from the LPC bridge.
+///
+/// Checks that the port is non-zero and that the full ACPI I/O register block fits in the I/O space. The SMI_EN
+/// register is then read back to check that the register block is decoded.
+///
+/// ## Returns
+///
+/// - `Ok(())` if the PMBASE is valid.
+/// - `Err(EfiError::DeviceError)` if any check fails.
+///
+fn validate_pmbase(pm_base: u16) -> patina::error::Result<()> {
+    if pm_base == 0 {
+        log::error!("ACPI (PMBASE) I/O Port is not programmed");
+        return Err(EfiError::DeviceError);
+    }
+    if pm_base as u32 + PMBASE_IO_SIZE > u16::MAX as u32 {
+        log::error!("ACPI (PMBASE) I/O Port {pm_base:#X} register block exceeds the I/O space");
+        return Err(EfiError::DeviceError);
+    }
+
+    validate_smi_en(pm_base)
+}
+
+/// Reads back SMI_EN to check that the ACPI I/O register block at `pm_base` is decoded.
+#[cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+fn validate_smi_en(pm_base: u16) -> patina::error::Result<()> {
+    let mut smi_en_port: x86_64::instructions::port::Port<u32> =
+        x86_64::instructions::port::Port::new(pm_base + register::ich9::PMBASE_OFS_SMI_EN as u16);
+    let smi_enable_val = unsafe { smi_en_port.read() };
+
+    check_smi_en(pm_base, smi_enable_val)
+}
+
+#[cfg(not(all(target_os = "uefi", target_arch = "x86_64", feature = "x64")))]
+fn validate_smi_en(_pm_base: u16) -> patina::error::Result<()> {
+    Ok(())
+}
+
+/// Checks the SMI_EN value `smi_en` read back from the ACPI I/O register block at `pm_base`.
+///
+/// ## Returns
+///
+/// - `Ok(())` if the register is decoded. The enable bits are not checked because the platform MM control
+///   initialization sets them.
+/// - `Err(EfiError::DeviceError)` if the register reads as all ones.
+///
+#[cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), test))]
+fn check_smi_en(pm_base: u16, smi_en: u32) -> patina::error::Result<()> {
+    if smi_en == u32::MAX {
+        log::error!("SMI_EN at ACPI (PMBASE) I/O Port {pm_base:#X} is not decoded");
+        return Err(EfiError::DeviceError);
+    }
+
+    Ok(())
+}
+
+/// Validates that the MM configuration is usable before it is locked and made available to MM consumers.
+///
+/// Checks that at least one communicate buffer is present, that each buffer is non-empty with a non-null, page-aligned
+/// address, that no two buffers overlap, and that the ACPI base is non-zero.
+///
+/// ## Returns
+///
+/// - `Ok(())` if the configuration is valid.
+/// - `Err(EfiError::InvalidParameter)` if any check fails.
+///
+fn validate(config: &MmCommunicationConfiguration) -> patina::error::Result<()> {
+    if config.comm_buffers.is_empty() {
+        log::error!("MM Configuration is invalid: no MM Communicate Buffers are present");
+        return Err(EfiError::InvalidParameter);
+    }
+
+    for buffer in config.comm_buffers.iter() {
+        let address = buffer.as_ptr() as usize;
+        if address == 0 {
+            log::error!("MM Configuration is invalid: MM Communicate Buffer {} has a null address", buffer.id());
+            return Err(EfiError::InvalidParameter);
+        }
+        if !address.is_multiple_of(UEFI_PAGE_SIZE) {
+            log::error!(
+                "MM Configuration is invalid: MM Communicate Buffer {} address {address:#X} is not page-aligned",
+                buffer.id()
+            );
+            return Err(EfiError::InvalidParameter);
+        }
+        if buffer.is_empty() {
+            log::error!("MM Configuration is invalid: MM Communicate Buffer {} has a length of zero", buffer.id());
+            return Err(EfiError::InvalidParameter);
+        }
+    }
+
+    let ranges: alloc::vec::Vec<Range<usize>> = config
+        .comm_buffers
+        .iter()
+        .map(|buffer| buffer.as_ptr() as usize..buffer.as_ptr() as usize + buffer.len())
+        .collect();
+    if let Some((first, second)) = find_overlap(&ranges) {
+        log::error!(
+            "MM Configuration is invalid: MM Communicate Buffer {} [{:#X}, {:#X}) overlaps MM Communicate Buffer {} \
+             [{:#X}, {:#X})",
+            config.comm_buffers[first].id(),
+            ranges[first].start,
+            ranges[first].end,
+            config.comm_buffers[second].id(),
+            ranges[second].start,
+            ranges[second].end
+        );
+        return Err(EfiError::InvalidParameter);
+    }
+
+    let acpi_base = match config.acpi_base {
+        AcpiBase::Io(port) => port as usize,
+        AcpiBase::Mmio(address) => address,
+    };
+    if acpi_base == 0 {
+        log::error!("MM Configuration is invalid: ACPI base is zero");
+        return Err(EfiError::InvalidParameter);
+    }
+
+    Ok(())
+}
+
+/// Returns the indices of the first two overlapping ranges in `ranges`, or `None` if no two ranges overlap.
+fn find_overlap(ranges: &[Range<usize>]) -> Option<(usize, usize)> {
+    ranges.iter().enumerate().find_map(|(i, range)| {
+        ranges
+            .iter()
+            .enumerate()
+            .skip(i + 1)
+            .find(|(_, other)| range.start < other.end && other.start < range.end)
+            .map(|(j, _)| (i, j))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use core::{alloc::Layout, pin::Pin};
+    use patina_mm::config::MmiPort;
+
+    /// Returns leaked, page-aligned memory of `pages` pages.
+    fn page_aligned_memory(pages: usize) -> &'static mut [u8] {
+        let layout = Layout::from_size_align(pages * UEFI_PAGE_SIZE, UEFI_PAGE_SIZE).unwrap();
+        // SAFETY: The layout has a non-zero size and the allocation is leaked, so the slice is valid for 'static.
+        unsafe { core::slice::from_raw_parts_mut(alloc::alloc::alloc_zeroed(layout), layout.size()) }
+    }
+
+    fn buffer(memory: &'static mut [u8], id: u8) -> CommunicateBuffer {
+        CommunicateBuffer::new(Pin::new(memory), id)
+    }
+
+    fn config(comm_buffers: alloc::vec::Vec<CommunicateBuffer>) -> MmCommunicationConfiguration {
+        MmCommunicationConfiguration {
+            acpi_base: AcpiBase::Io(0x600),
+            cmd_port: MmiPort::Smi(0xB2),
+            comm_buffers,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_config_is_accepted() {
+        let config = config(vec![buffer(page_aligned_memory(1), 0), buffer(page_aligned_memory(4), 1)]);
+        assert_eq!(validate(&config), Ok(()));
+    }
+
+    #[test]
+    fn config_without_buffers_is_rejected() {
+        assert_eq!(validate(&config(vec![])), Err(EfiError::InvalidParameter));
+    }
+
+    #[test]
+    fn unaligned_buffer_is_rejected() {
+        let memory = page_aligned_memory(2);
+        let config = config(vec![buffer(&mut memory[8..], 0)]);
+        assert_eq!(validate(&config), Err(EfiError::InvalidParameter));
+    }
+
+    #[test]
+    fn empty_buffer_is_rejected() {
+        let memory = page_aligned_memory(1);
+        let config = config(vec![buffer(&mut memory[..0], 0)]);
+        assert_eq!(validate(&config), Err(EfiError::InvalidParameter));
+    }
+
+    #[test]
+    fn zero_acpi_base_is_rejected() {
+        let mut config = config(vec![buffer(page_aligned_memory(1), 0)]);
+        config.acpi_base = AcpiBase::Io(0);
+        assert_eq!(validate(&config), Err(EfiError::InvalidParameter));
+
+        config.acpi_base = AcpiBase::Mmio(0);
+        assert_eq!(validate(&config), Err(EfiError::InvalidParameter));
+    }
+
+    fn region_hob(buffer_type: u64, address: u64, pages: u64) -> MmCommRegionHob {
+        MmCommRegionHob { buffer_type, address, pages }
+    }
+
+    #[test]
+    fn known_buffer_types_round_trip() {
+        for (value, buffer_type) in
+            [(0, MmBufferType::General), (1, MmBufferType::RingBuffer), (2, MmBufferType::MmSupervisor)]
+        {
+            assert_eq!(MmBufferType::from(value), buffer_type);
+            assert_eq!(u8::from(buffer_type), value);
+            assert_eq!(region_hob(value as u64, 0x1000, 1).buffer_type_enum(), Some(buffer_type));
+        }
+    }
+
+    #[test]
+    fn unknown_buffer_types_are_passed_through() {
+        assert_eq!(MmBufferType::from(7), MmBufferType::Other(7));
+        assert_eq!(u8::from(MmBufferType::Other(7)), 7);
+        assert_eq!(region_hob(0xFF, 0x1000, 1).buffer_type_enum(), Some(MmBufferType::Other(0xFF)));
+    }
+
+    #[test]
+    fn buffer_types_wider_than_an_id_are_rejected() {
+        assert_eq!(MmBufferType::try_from(0x100u64), Err(0x100));
+        assert_eq!(region_hob(0x1_0000_0002, 0x1000, 1).buffer_type_enum(), None);
+    }
+
+    #[test]
+    fn total_and_largest_size_of_three_hobs() {
+        let hobs = [region_hob(0, 0x10_0000, 4), region_hob(1, 0x20_0000, 16), region_hob(2, 0x40_0000, 256)];
+        assert_eq!(MmCommRegionHob::total_size(hobs.iter().copied()), (4 + 16 + 256) * UEFI_PAGE_SIZE);
+        assert_eq!(MmCommRegionHob::largest_buffer(hobs.iter().copied()), Some(256 * UEFI_PAGE_SIZE));
+    }
+
+    #[test]
+    fn size_statistics_of_no_hobs() {
+        assert_eq!(MmCommRegionHob::total_size(core::iter::empty()), 0);
+        assert_eq!(MmCommRegionHob::largest_buffer(core::iter::empty()), None);
+    }
+
+    fn hob_ranges(hobs: &[MmCommRegionHob]) -> alloc::vec::Vec<Range<usize>> {
+        hobs.iter().map(MmCommRegionHob::range).collect()
+    }
+
+    #[test]
+    fn non_overlapping_hobs_are_accepted() {
+        let hobs = [region_hob(0, 0x10_0000, 4), region_hob(1, 0x10_4000, 16), region_hob(2, 0x20_0000, 1)];
+        assert_eq!(find_overlap(&hob_ranges(&hobs)), None);
+    }
+
+    #[test]
+    fn overlapping_hobs_are_detected() {
+        let hobs = [region_hob(0, 0x10_0000, 4), region_hob(1, 0x20_0000, 1), region_hob(2, 0x10_3000, 2)];
+        assert_eq!(find_overlap(&hob_ranges(&hobs)), Some((0, 2)));
+    }
+
+    #[test]
+    fn duplicate_hobs_are_detected() {
+        let hobs = [region_hob(0, 0x10_0000, 4), region_hob(1, 0x10_0000, 4)];
+        assert_eq!(find_overlap(&hob_ranges(&hobs)), Some((0, 1)));
+    }
+
+    #[test]
+    fn valid_pmbase_is_accepted() {
+        assert!(validate_pmbase(0x600).is_ok());
+        assert!(validate_pmbase(0xFF7C).is_ok());
+    }
+
+    #[test]
+    fn zero_pmbase_is_rejected() {
+        assert_eq!(validate_pmbase(0), Err(EfiError::DeviceError));
+    }
+
+    #[test]
+    fn pmbase_register_block_must_fit_in_io_space() {
+        assert_eq!(validate_pmbase(0xFF80), Err(EfiError::DeviceError));
+        assert_eq!(validate_pmbase(0xFFC0), Err(EfiError::DeviceError));
+    }
+
+    #[test]
+    fn undecoded_smi_en_is_rejected() {
+        assert_eq!(check_smi_en(0x600, u32::MAX), Err(EfiError::DeviceError));
+    }
+
+    #[test]
+    fn decoded_smi_en_is_accepted() {
+        assert!(check_smi_en(0x600, 0).is_ok());
+        assert!(check_smi_en(0x600, register::ich9::SMI_EN_APMC_EN).is_ok());
+        assert!(check_smi_en(0x600, register::ich9::SMI_EN_GBL_SMI_EN).is_ok());
+        assert!(check_smi_en(0x600, register::ich9::SMI_EN_APMC_EN | register::ich9::SMI_EN_GBL_SMI_EN).is_ok());
+    }
+}
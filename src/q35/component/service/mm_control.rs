@@ -8,11 +8,14 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
-#![cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
 
-use patina_mm::{config::MmCommunicationConfiguration, service::platform_mm_control::PlatformMmControl};
+use patina_mm::{
+    config::{MmCommunicationConfiguration, MmiPort},
+    service::platform_mm_control::PlatformMmControl,
+};
 
-use crate::q35::registers as register;
+use crate::q35::{registers as register, timer};
 use patina::component::{Storage, component, service::IntoService};
 
 use x86_64::instructions::port::Port;
@@ -37,6 +40,11 @@ impl QemuQ35PlatformMmControl {
         Self::default()
     }
 
+    /// Creates a new instance of the QEMU Q35 platform MM control component for the MM configuration `config`.
+    pub fn with_config(config: MmCommunicationConfiguration) -> Self {
+        Self { inner_config: config }
+    }
+
     /// Entry point for the QEMU Q35 platform MM control component.
     ///
     /// Installs an instance of the `PlatformMmControl` service that can be invoked by other components that depend
@@ -54,6 +62,196 @@ impl QemuQ35PlatformMmControl {
 
         Ok(())
     }
+
+    /// Logs the ICH9 SMI and power management register state.
+    ///
+    /// Intended to be used when MM communication fails to help determine whether SMIs are being generated.
+    pub fn diagnostic_dump(&self) {
+        diagnostic_dump(self.inner_config.acpi_base.get_io_value());
+    }
+
+    /// Returns whether the SMI Lock bit in GEN_PMCON_1 is set.
+    ///
+    /// Intended to be used after [`PlatformMmControl::init`] to confirm that the hardware accepted the lock.
+    pub fn verify_smi_lock(&self) -> bool {
+        // SAFETY: GEN_PMCON_1 is in the LPC bridge configuration space within the ECAM region.
+        let gen_pmcon_1_val = unsafe { register::ich9::LPC.cfg_read16(register::ich9::GEN_PMCON_1 as u16) };
+        gen_pmcon_1_val & register::ich9::GEN_PMCON_1_SMI_LOCK != 0
+    }
+}
+
+/// Access to the ICH9 SMI_EN register, abstracted so the SMI enable sequence can be tested without hardware.
+trait SmiEnAccess {
+    /// Reads SMI_EN.
+    fn read(&mut self) -> u32;
+    /// Writes `value` to SMI_EN.
+    fn write(&mut self, value: u32);
+    /// Waits before a failed read is retried.
+    fn retry_delay(&mut self);
+}
+
+/// The SMI_EN I/O port in the ACPI I/O register block at `pm_base`.
+struct SmiEnPort {
+    port: Port<u32>,
+    pm_base: u16,
+}
+
+impl SmiEnPort {
+    fn new(pm_base: u16) -> Self {
+        Self { port: Port::new(pm_base + register::ich9::PMBASE_OFS_SMI_EN as u16), pm_base }
+    }
+}
+
+impl SmiEnAccess for SmiEnPort {
+    fn read(&mut self) -> u32 {
+        // SAFETY: SMI_EN is a fixed offset from PMBASE.
+        unsafe { self.port.read() }
+    }
+
+    fn write(&mut self, value: u32) {
+        // SAFETY: SMI_EN is a fixed offset from PMBASE.
+        unsafe { self.port.write(value) }
+    }
+
+    fn retry_delay(&mut self) {
+        const SMI_EN_RETRY_DELAY_US: u64 = 1000;
+
+        // SAFETY: The PM timer is a fixed offset from PMBASE.
+        unsafe { timer::stall(self.pm_base + register::ich9::PM1_TMR, SMI_EN_RETRY_DELAY_US) };
+    }
+}
+
+/// Maximum number of SMI_EN reads before a bus error is reported.
+const SMI_EN_READ_ATTEMPTS: usize = 4;
+
+/// Reads SMI_EN, retrying if the read reports a bus error (all ones).
+fn read_smi_en(smi_en: &mut impl SmiEnAccess) -> patina::error::Result<u32> {
+    for attempt in 0..SMI_EN_READ_ATTEMPTS {
+        if attempt > 0 {
+            smi_en.retry_delay();
+        }
+        let smi_enable_val = smi_en.read();
+        if smi_enable_val != u32::MAX {
+            return Ok(smi_enable_val);
+        }
+        log::warn!("SMI_EN read returned {smi_enable_val:#X} (attempt {})", attempt + 1);
+    }
+
+    log::error!("SMI_EN read failed after {SMI_EN_READ_ATTEMPTS} attempts");
+    Err(patina::error::EfiError::DeviceError)
+}
+
+/// Enables APM and global SMIs in SMI_EN and checks that the write took effect.
+fn enable_smis(smi_en: &mut impl SmiEnAccess) -> patina::error::Result<()> {
+    let smi_enable_val = read_smi_en(smi_en)?;
+
+    // On Q35, the SMI_EN bit should be set already if Standalone MM was launched in PEI.
+    if smi_enable_val & register::ich9::SMI_EN_APMC_EN != 0 {
+        assert_eq!(
+            smi_enable_val & register::ich9::SMI_EN_GBL_SMI_EN,
+            register::ich9::SMI_EN_GBL_SMI_EN,
+            "SMI Enable bit not set"
+        );
+    }
+
+    // In any case, set the SMI_EN bit to enable SMI generation.
+    let smi_enable_val = smi_enable_val | register::ich9::SMI_EN_APMC_EN | register::ich9::SMI_EN_GBL_SMI_EN;
+    smi_en.write(smi_enable_val);
+
+    let smi_enable_readback = read_smi_en(smi_en)?;
+    if smi_enable_readback & register::ich9::SMI_EN_GBL_SMI_EN == 0 {
+        log::error!("SMI_EN write of {smi_enable_val:#X} did not take effect (read back {smi_enable_readback:#X})");
+        return Err(patina::error::EfiError::DeviceError);
+    }
+
+    Ok(())
+}
+
+/// Returns the I/O port of the MM command port in `config`, or `None` if it is not an SMI port.
+pub fn cmd_port_io_address(config: &MmCommunicationConfiguration) -> Option<u16> {
+    match config.cmd_port {
+        MmiPort::Smi(port) => Some(port),
+        MmiPort::Smc(_) => None,
+    }
+}
+
+/// Checks that the MM command port in `config` is the ICH9 APM_CNT port, which is the only port that generates
+/// software MMIs on Q35.
+///
+/// ## Returns
+///
+/// - `Ok(())` if the command port is the APM_CNT port.
+/// - `Err(EfiError::InvalidParameter)` otherwise.
+///
+fn validate_cmd_port(config: &MmCommunicationConfiguration) -> patina::error::Result<()> {
+    if cmd_port_io_address(config) != Some(register::ich9::APM_CNT) {
+        log::error!(
+            "MM command port {} does not match the ICH9 APM_CNT port {:#X}",
+            config.cmd_port,
+            register::ich9::APM_CNT
+        );
+        return Err(patina::error::EfiError::InvalidParameter);
+    }
+
+    Ok(())
+}
+
+/// The ICH9 SMI and power management register state logged by [`diagnostic_dump`].
+#[derive(Debug, Clone, Copy, Default)]
+struct MmDiagnosticRegisters {
+    pm_base: u16,
+    pm1_sts: u16,
+    pm1_en: u16,
+    pm1_cnt: u16,
+    smi_en: u32,
+    smi_sts: u32,
+    gen_pmcon_1: u16,
+}
+
+impl core::fmt::Display for MmDiagnosticRegisters {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "MM Diagnostic Dump (PMBASE: {:#06X}):", self.pm_base)?;
+        writeln!(f, "  PM1_STS:     {:#06X}", self.pm1_sts)?;
+        writeln!(f, "  PM1_EN:      {:#06X}", self.pm1_en)?;
+        writeln!(
+            f,
+            "  PM1_CNT:     {:#06X} (SCI_EN: {})",
+            self.pm1_cnt,
+            self.pm1_cnt & register::ich9::PM1_CNT_SCI_EN != 0
+        )?;
+        writeln!(
+            f,
+            "  SMI_EN:      {:#010X} (GBL_SMI_EN: {}, APMC_EN: {})",
+            self.smi_en,
+            self.smi_en & register::ich9::SMI_EN_GBL_SMI_EN != 0,
+            self.smi_en & register::ich9::SMI_EN_APMC_EN != 0
+        )?;
+        writeln!(f, "  SMI_STS:     {:#010X}", self.smi_sts)?;
+        write!(
+            f,
+            "  GEN_PMCON_1: {:#06X} (SMI_LOCK: {})",
+            self.gen_pmcon_1,
+            self.gen_pmcon_1 & register::ich9::GEN_PMCON_1_SMI_LOCK != 0
+        )
+    }
+}
+
+/// Logs the ICH9 SMI and power management register state for the ACPI (PMBASE) I/O port `pm_base`.
+pub fn diagnostic_dump(pm_base: u16) {
+    // SAFETY: The PM1 and SMI registers are fixed offsets from PMBASE and reading them has no side effects.
+    let registers = unsafe {
+        MmDiagnosticRegisters {
+            pm_base,
+            pm1_sts: Port::<u16>::new(pm_base + register::ich9::PM1_STS).read(),
+            pm1_en: Port::<u16>::new(pm_base + register::ich9::PM1_EN).read(),
+            pm1_cnt: Port::<u16>::new(pm_base + register::ich9::PM1A_CNT).read(),
+            smi_en: Port::<u32>::new(pm_base + register::ich9::PMBASE_OFS_SMI_EN as u16).read(),
+            smi_sts: Port::<u32>::new(pm_base + register::ich9::PMBASE_OFS_SMI_STS as u16).read(),
+            gen_pmcon_1: register::ich9::LPC.cfg_read16(register::ich9::GEN_PMCON_1 as u16),
+        }
+    };
+
+    log::info!("{registers}");
 }
 
 impl PlatformMmControl for QemuQ35PlatformMmControl {
@@ -63,31 +261,159 @@ impl PlatformMmControl for QemuQ35PlatformMmControl {
     fn init(&self) -> patina::error::Result<()> {
         log::debug!("Performing platform-specific MM init...");
 
-        let mut smi_en_port =
-            Port::new(self.inner_config.acpi_base.get_io_value() + register::ich9::PMBASE_OFS_SMI_EN as u16);
-        let smi_enable_val: u32 = unsafe { smi_en_port.read() };
-
-        // On Q35, the SMI_EN bit should be set already if Standalone MM was launched in PEI.
-        if smi_enable_val & register::ich9::SMI_EN_APMC_EN != 0 {
-            assert_eq!(
-                smi_enable_val & register::ich9::SMI_EN_GBL_SMI_EN,
-                register::ich9::SMI_EN_GBL_SMI_EN,
-                "SMI Enable bit not set"
-            );
-        }
+        validate_cmd_port(&self.inner_config)?;
 
-        // In any case, set the SMI_EN bit to enable SMI generation.
-        let smi_enable_val = smi_enable_val | register::ich9::SMI_EN_APMC_EN | register::ich9::SMI_EN_GBL_SMI_EN;
-        unsafe { smi_en_port.write(smi_enable_val) };
+        enable_smis(&mut SmiEnPort::new(self.inner_config.acpi_base.get_io_value()))?;
 
-        // Set the SMI Lock bit in the PM1A_CNT register to lock the SMI_EN bits
-        let pm1a_cnt: *mut u16 = (register::PCI_EXPRESS_BASE_ADDRESS as usize
-            + patina::pci_address!(0, 0x1F, 0, register::ich9::GEN_PMCON_1) as usize)
-            as *mut u16;
-        let mut pm1a_cnt_val: u16 = unsafe { core::ptr::read_volatile(pm1a_cnt) };
-        pm1a_cnt_val |= register::ich9::GEN_PMCON_1_SMI_LOCK;
-        unsafe { core::ptr::write_volatile(pm1a_cnt, pm1a_cnt_val) };
+        // Set the SMI Lock bit in the GEN_PMCON_1 register to lock the SMI_EN bits
+        let lpc = register::ich9::LPC;
+        let mut gen_pmcon_1_val = unsafe { lpc.cfg_read16(register::ich9::GEN_PMCON_1 as u16) };
+        gen_pmcon_1_val |= register::ich9::GEN_PMCON_1_SMI_LOCK;
+        unsafe {
+            register::ecam_write(lpc.bus, lpc.device, lpc.function, register::ich9::GEN_PMCON_1 as u16, gen_pmcon_1_val)
+        };
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+    use alloc::{collections::VecDeque, vec::Vec};
+
+    use super::*;
+    use patina::error::EfiError;
+
+    fn config_with_cmd_port(cmd_port: MmiPort) -> MmCommunicationConfiguration {
+        MmCommunicationConfiguration { cmd_port, ..Default::default() }
+    }
+
+    #[test]
+    fn cmd_port_io_address_extracts_smi_port() {
+        assert_eq!(cmd_port_io_address(&config_with_cmd_port(MmiPort::Smi(0xB2))), Some(0xB2));
+    }
+
+    #[test]
+    fn cmd_port_io_address_rejects_smc() {
+        assert_eq!(cmd_port_io_address(&config_with_cmd_port(MmiPort::Smc(0xC400_0041))), None);
+    }
+
+    #[test]
+    fn apm_cnt_cmd_port_is_accepted() {
+        assert_eq!(validate_cmd_port(&config_with_cmd_port(MmiPort::Smi(register::ich9::APM_CNT))), Ok(()));
+    }
+
+    #[test]
+    fn other_cmd_ports_are_rejected() {
+        assert_eq!(validate_cmd_port(&config_with_cmd_port(MmiPort::Smi(0xB3))), Err(EfiError::InvalidParameter));
+        assert_eq!(
+            validate_cmd_port(&config_with_cmd_port(MmiPort::Smc(0xC400_0041))),
+            Err(EfiError::InvalidParameter)
+        );
+    }
+
+    /// A mock SMI_EN register that returns `reads` in order, then all ones.
+    #[derive(Default)]
+    struct MockSmiEn {
+        reads: VecDeque<u32>,
+        writes: Vec<u32>,
+        delays: usize,
+    }
+
+    impl MockSmiEn {
+        fn with_reads(reads: &[u32]) -> Self {
+            Self { reads: reads.iter().copied().collect(), ..Default::default() }
+        }
+    }
+
+    impl SmiEnAccess for MockSmiEn {
+        fn read(&mut self) -> u32 {
+            self.reads.pop_front().unwrap_or(u32::MAX)
+        }
+
+        fn write(&mut self, value: u32) {
+            self.writes.push(value);
+        }
+
+        fn retry_delay(&mut self) {
+            self.delays += 1;
+        }
+    }
+
+    const SMI_EN_ENABLED: u32 = register::ich9::SMI_EN_APMC_EN | register::ich9::SMI_EN_GBL_SMI_EN;
+
+    #[test]
+    fn smi_en_read_succeeds_on_last_attempt() {
+        let mut smi_en = MockSmiEn::with_reads(&[u32::MAX, u32::MAX, u32::MAX, 0x1]);
+        assert_eq!(read_smi_en(&mut smi_en), Ok(0x1));
+        assert_eq!(smi_en.delays, SMI_EN_READ_ATTEMPTS - 1);
+    }
+
+    #[test]
+    fn smi_en_read_fails_after_all_attempts() {
+        let mut smi_en = MockSmiEn::with_reads(&[u32::MAX, u32::MAX, u32::MAX, u32::MAX, 0x1]);
+        assert_eq!(read_smi_en(&mut smi_en), Err(EfiError::DeviceError));
+        assert_eq!(smi_en.delays, SMI_EN_READ_ATTEMPTS - 1);
+        assert_eq!(smi_en.reads.len(), 1);
+    }
+
+    #[test]
+    fn smi_en_read_does_not_retry_valid_value() {
+        let mut smi_en = MockSmiEn::with_reads(&[0]);
+        assert_eq!(read_smi_en(&mut smi_en), Ok(0));
+        assert_eq!(smi_en.delays, 0);
+    }
+
+    #[test]
+    fn enable_smis_sets_apmc_and_global_enable() {
+        let mut smi_en = MockSmiEn::with_reads(&[0x8, 0x8 | SMI_EN_ENABLED]);
+        assert_eq!(enable_smis(&mut smi_en), Ok(()));
+        assert_eq!(smi_en.writes, [0x8 | SMI_EN_ENABLED]);
+    }
+
+    #[test]
+    fn enable_smis_rejects_missing_global_enable_readback() {
+        let mut smi_en = MockSmiEn::with_reads(&[0, register::ich9::SMI_EN_APMC_EN]);
+        assert_eq!(enable_smis(&mut smi_en), Err(EfiError::DeviceError));
+        assert_eq!(smi_en.writes, [SMI_EN_ENABLED]);
+    }
+
+    #[test]
+    fn enable_smis_rejects_failed_readback() {
+        let mut smi_en = MockSmiEn::with_reads(&[0]);
+        assert_eq!(enable_smis(&mut smi_en), Err(EfiError::DeviceError));
+    }
+
+    #[test]
+    fn diagnostic_dump_formats_every_register() {
+        let registers = MmDiagnosticRegisters {
+            pm_base: 0x600,
+            pm1_sts: 0x0100,
+            pm1_en: 0x0120,
+            pm1_cnt: register::ich9::PM1_CNT_SCI_EN,
+            smi_en: SMI_EN_ENABLED,
+            smi_sts: 0x20,
+            gen_pmcon_1: register::ich9::GEN_PMCON_1_SMI_LOCK,
+        };
+
+        assert_eq!(
+            alloc::format!("{registers}"),
+            "MM Diagnostic Dump (PMBASE: 0x0600):\n\
+             \x20 PM1_STS:     0x0100\n\
+             \x20 PM1_EN:      0x0120\n\
+             \x20 PM1_CNT:     0x0001 (SCI_EN: true)\n\
+             \x20 SMI_EN:      0x00000021 (GBL_SMI_EN: true, APMC_EN: true)\n\
+             \x20 SMI_STS:     0x00000020\n\
+             \x20 GEN_PMCON_1: 0x0010 (SMI_LOCK: true)"
+        );
+    }
+
+    #[test]
+    fn diagnostic_dump_reports_cleared_bits() {
+        let formatted = alloc::format!("{}", MmDiagnosticRegisters::default());
+        assert!(formatted.contains("(SCI_EN: false)"));
+        assert!(formatted.contains("(GBL_SMI_EN: false, APMC_EN: false)"));
+        assert!(formatted.contains("(SMI_LOCK: false)"));
+    }
+}
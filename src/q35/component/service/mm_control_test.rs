@@ -0,0 +1,33 @@
+//! QEMU Q35 Platform MM Control Test
+//!
+//! Verifies that the SMI Lock bit is set after the QEMU Q35 platform MM control initialization.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+
+use patina::component::params::Config;
+use patina_mm::{config::MmCommunicationConfiguration, service::platform_mm_control::PlatformMmControl};
+use patina_test::patina_test;
+
+use super::mm_control::QemuQ35PlatformMmControl;
+
+/// Tests that the SMI Lock bit reads back as set after `init`.
+///
+/// QEMU does not enforce the SMI Lock bit on all machine versions, so a lock that did not stick is only reported as
+/// a warning.
+#[patina_test]
+fn q35_mm_control_smi_lock_test(config: Config<MmCommunicationConfiguration>) -> patina_test::error::Result {
+    let mm_control = QemuQ35PlatformMmControl::with_config((*config).clone());
+    mm_control.init().map_err(|_| "Platform MM control init failed")?;
+
+    if !mm_control.verify_smi_lock() {
+        log::warn!("SMI Lock bit is not set after platform MM control init");
+    }
+
+    Ok(())
+}
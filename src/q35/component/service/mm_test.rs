@@ -10,8 +10,21 @@
 //! SPDX-License-Identifier: Apache-2.0
 //!
 
-use patina::component::{component, service::Service};
-use patina_mm::service::MmCommunication;
+extern crate alloc;
+use alloc::{boxed::Box, vec::Vec};
+
+use patina::component::{component, params::Config, service::Service};
+use patina_mm::{component::communicator::Status, config::MmCommunicationConfiguration, service::MmCommunication};
+use patina_test::{patina_test, u_assert_eq};
+
+use super::smm_verify::SmmModeVerified;
+
+/// Minimum MM Supervisor version required by the DXE MM components on QEMU Q35.
+pub const MIN_MM_SUPERVISOR_VERSION: u32 = 0x0001_0002;
+
+/// GUID of the MM Supervisor request handler.
+pub(crate) const MM_SUPERVISOR_REQUEST_HANDLER_GUID: patina::BinaryGuid =
+    patina::BinaryGuid::from_string("8C633B23-1260-4EA6-830F-7DDC97382111");
 
 /// MM Supervisor Request Header
 ///
@@ -21,12 +34,12 @@ use patina_mm::service::MmCommunication;
 ///
 /// - This structure is only defined here for test purposes.
 #[repr(C, packed(1))]
-struct MmSupervisorRequestHeader {
-    signature: u32,
-    revision: u32,
-    request: u32,
-    reserved: u32,
-    result: u64,
+pub(crate) struct MmSupervisorRequestHeader {
+    pub(crate) signature: u32,
+    pub(crate) revision: u32,
+    pub(crate) request: u32,
+    pub(crate) reserved: u32,
+    pub(crate) result: u64,
 }
 
 /// MM Supervisor Version Info
@@ -60,7 +73,15 @@ impl QemuQ35MmTest {
     ///
     /// Uses the `MmCommunication` service to send a request version information from the MM Supervisor. The MM
     /// Supervisor is expected to be the Standalone MM environment used on the QEMU Q35 platform.
-    pub fn entry_point(self, mm_comm: Service<dyn MmCommunication>) -> patina::error::Result<()> {
+    ///
+    /// Only dispatched once [`SmmModeVerifier`](super::smm_verify::SmmModeVerifier) has confirmed that software SMIs
+    /// are handled. If the transaction fails, the SMI register state is logged to aid debugging.
+    pub fn entry_point(
+        self,
+        mm_comm: Service<dyn MmCommunication>,
+        config: Config<MmCommunicationConfiguration>,
+        _smm_verified: Config<SmmModeVerified>,
+    ) -> patina::error::Result<()> {
         log::debug!("MM Test Entry Point - Testing MM Communication");
 
         let mm_supv_req_header = MmSupervisorRequestHeader {
@@ -79,18 +100,16 @@ impl QemuQ35MmTest {
                         &mm_supv_req_header as *const _ as *const u8,
                         core::mem::size_of::<MmSupervisorRequestHeader>(),
                     ),
-                    patina::Guid::from_fields(
-                        0x8c633b23,
-                        0x1260,
-                        0x4ea6,
-                        0x83,
-                        0x0F,
-                        [0x7d, 0xdc, 0x97, 0x38, 0x21, 0x11],
-                    ),
+                    patina::Guid::from(&MM_SUPERVISOR_REQUEST_HANDLER_GUID),
                 )
-                .map_err(|_| {
-                    log::error!("MM Communication failed");
-                    patina::error::EfiError::DeviceError // Todo: Map actual codes
+                .map_err(|status| {
+                    log::error!("MM Communication failed with {status:?} (ACPI base: {})", config.acpi_base);
+                    let error = communicate_error(status);
+                    #[cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+                    if error == patina::error::EfiError::DeviceError {
+                        super::mm_control::diagnostic_dump(config.acpi_base.get_io_value());
+                    }
+                    error
                 })?
         };
 
@@ -104,6 +123,102 @@ impl QemuQ35MmTest {
             "MM Supervisor Version: {version:#X}, Patch Level: {patch_level:#X}, Max Request Level: {max_request_level:#X}",
         );
 
+        Self::version_check(version)
+    }
+
+    /// Checks that the MM Supervisor `version` is at least [`MIN_MM_SUPERVISOR_VERSION`].
+    ///
+    /// ## Returns
+    ///
+    /// - `Ok(())` if the version is supported.
+    /// - `Err(EfiError::IncompatibleError)` (`EFI_INCOMPATIBLE_VERSION`) if the MM Supervisor is too old.
+    ///
+    pub fn version_check(version: u32) -> patina::error::Result<()> {
+        if version < MIN_MM_SUPERVISOR_VERSION {
+            log::error!(
+                "MM Supervisor version {version:#010X} is older than the minimum supported version \
+                 {MIN_MM_SUPERVISOR_VERSION:#010X}"
+            );
+            return Err(patina::error::EfiError::IncompatibleError);
+        }
         Ok(())
     }
 }
+
+/// Maps the MM communication `status` to the closest [`EfiError`](patina::error::EfiError).
+///
+/// Only failures to trigger or complete the MMI map to `DeviceError`.
+fn communicate_error(status: Status) -> patina::error::EfiError {
+    match status {
+        Status::NoCommBuffer | Status::CommBufferNotFound => patina::error::EfiError::NotFound,
+        Status::CommBufferTooSmall => patina::error::EfiError::BufferTooSmall,
+        Status::CommBufferInitError | Status::InvalidDataBuffer => patina::error::EfiError::InvalidParameter,
+        Status::SwMmiServiceNotAvailable => patina::error::EfiError::NotReady,
+        Status::SwMmiFailed | Status::InvalidResponse => patina::error::EfiError::DeviceError,
+    }
+}
+
+/// An MM communication service that answers every request with a successful MM Supervisor version response.
+struct MockMmSupervisor {
+    version: u32,
+}
+
+impl MmCommunication for MockMmSupervisor {
+    fn communicate<'a>(&self, _id: u8, data_buffer: &[u8], _recipient: patina::Guid<'a>) -> Result<Vec<u8>, Status> {
+        let header_size = core::mem::size_of::<MmSupervisorRequestHeader>();
+        let mut response = data_buffer.get(..header_size).ok_or(Status::InvalidDataBuffer)?.to_vec();
+        // Report success in the request header `result` field.
+        response[header_size - core::mem::size_of::<u64>()..].fill(0);
+        response.extend_from_slice(&self.version.to_le_bytes());
+        response.extend_from_slice(&0u32.to_le_bytes());
+        response.extend_from_slice(&0u64.to_le_bytes());
+        Ok(response)
+    }
+}
+
+/// Tests that the MM test component rejects an MM Supervisor older than [`MIN_MM_SUPERVISOR_VERSION`].
+#[patina_test]
+fn q35_mm_supervisor_min_version_test() -> patina_test::error::Result {
+    let result = QemuQ35MmTest::new().entry_point(
+        Service::mock(Box::new(MockMmSupervisor { version: 0x0001_0001 })),
+        Config::mock(MmCommunicationConfiguration::default()),
+        Config::mock(SmmModeVerified),
+    );
+    u_assert_eq!(result, Err(patina::error::EfiError::IncompatibleError), "MM Supervisor 0x00010001 was accepted");
+
+    let result = QemuQ35MmTest::new().entry_point(
+        Service::mock(Box::new(MockMmSupervisor { version: MIN_MM_SUPERVISOR_VERSION })),
+        Config::mock(MmCommunicationConfiguration::default()),
+        Config::mock(SmmModeVerified),
+    );
+    u_assert_eq!(result, Ok(()), "Minimum MM Supervisor version was rejected");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_check_enforces_minimum() {
+        assert_eq!(QemuQ35MmTest::version_check(0x0001_0001), Err(patina::error::EfiError::IncompatibleError));
+        assert_eq!(QemuQ35MmTest::version_check(MIN_MM_SUPERVISOR_VERSION), Ok(()));
+        assert_eq!(QemuQ35MmTest::version_check(0x0002_0000), Ok(()));
+    }
+
+    #[test]
+    fn supervisor_request_handler_guid_matches_its_fields() {
+        assert_eq!(
+            MM_SUPERVISOR_REQUEST_HANDLER_GUID,
+            patina::BinaryGuid::from_fields(
+                0x8C633B23,
+                0x1260,
+                0x4EA6,
+                0x83,
+                0x0F,
+                &[0x7D, 0xDC, 0x97, 0x38, 0x21, 0x11]
+            )
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! QEMU Q35 PCI Express Test
+//!
+//! Verifies that PCI Express configuration space is accessible through ECAM on the QEMU Q35 platform.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina_test::{patina_test, u_assert_eq};
+
+use crate::q35::registers::{self as register, PciBdf};
+
+/// Vendor ID of the Q35 host bridge (Intel).
+const Q35_HOST_BRIDGE_VENDOR_ID: u16 = 0x8086;
+
+/// Tests that the Vendor ID of the host bridge (00:00.0) reads back through ECAM.
+#[patina_test]
+fn q35_ecam_host_bridge_vendor_id_test() -> patina_test::error::Result {
+    let host_bridge = PciBdf::new(0, 0, 0);
+
+    // SAFETY: The ECAM region is mapped on QEMU Q35 and the Vendor ID register is 16-bit aligned.
+    let vendor_id = unsafe { host_bridge.cfg_read16(register::PCI_VENDOR_ID) };
+    log::debug!("Host bridge Vendor ID: {vendor_id:#06X}");
+
+    u_assert_eq!(vendor_id, Q35_HOST_BRIDGE_VENDOR_ID, "Host bridge Vendor ID is not Intel");
+
+    Ok(())
+}
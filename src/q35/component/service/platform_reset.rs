@@ -0,0 +1,95 @@
+//! QEMU Q35 Platform Reset
+//!
+//! Provides the [`PlatformReset`] service, which powers off the platform by entering the ACPI S5 (soft off) sleep
+//! state through the ICH9 PM1 control register.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
+
+use patina::component::{
+    component,
+    params::{Commands, Config},
+    service::IntoService,
+};
+use patina_mm::config::MmCommunicationConfiguration;
+use x86_64::instructions::port::Port;
+
+use crate::q35::registers as register;
+
+/// Resets the platform.
+pub trait PlatformReset {
+    /// Powers off the platform.
+    fn shutdown(&self);
+}
+
+/// The QEMU Q35 platform reset component.
+///
+/// Installs itself as the [`PlatformReset`] service once the ACPI (PMBASE) I/O port is known.
+#[derive(IntoService, Default)]
+#[service(dyn PlatformReset)]
+pub struct QemuQ35PlatformReset {
+    pm_base: u16,
+}
+
+#[component]
+impl QemuQ35PlatformReset {
+    /// Creates a new instance of the QEMU Q35 platform reset component.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Entry point for the QEMU Q35 platform reset component.
+    ///
+    /// Depends on the locked `MmCommunicationConfiguration` for the ACPI (PMBASE) I/O port.
+    fn entry_point(
+        mut self,
+        config: Config<MmCommunicationConfiguration>,
+        mut commands: Commands,
+    ) -> patina::error::Result<()> {
+        log::debug!("Platform Reset Entry Point");
+
+        self.pm_base = config.acpi_base.get_io_value();
+        commands.add_service(self);
+
+        Ok(())
+    }
+}
+
+impl PlatformReset for QemuQ35PlatformReset {
+    fn shutdown(&self) {
+        log::info!("Shutting down");
+        let mut pm1_cnt_port: Port<u16> = Port::new(self.pm_base + register::ich9::PM1A_CNT);
+        // SAFETY: PM1_CNT is a fixed offset from PMBASE. Entering S5 powers off the platform.
+        unsafe {
+            let pm1_cnt = pm1_cnt_port.read();
+            pm1_cnt_port.write(s5_sleep_value(pm1_cnt));
+        }
+    }
+}
+
+/// Returns the PM1 control register value that enters S5 given the current value `pm1_cnt`.
+pub fn s5_sleep_value(pm1_cnt: u16) -> u16 {
+    (pm1_cnt & !register::ich9::PM1_CNT_SLP_TYP_MASK) | register::ich9::SLP_TYP_S5 | register::ich9::SLP_EN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use register::ich9::{PM1_CNT_SCI_EN, PM1_CNT_SLP_TYP_MASK, SLP_EN};
+
+    #[test]
+    fn s5_sleep_value_uses_qemu_sleep_type() {
+        assert_eq!(s5_sleep_value(0), SLP_EN);
+        assert_eq!(s5_sleep_value(PM1_CNT_SCI_EN), PM1_CNT_SCI_EN | SLP_EN);
+    }
+
+    #[test]
+    fn s5_sleep_value_replaces_previous_sleep_type() {
+        assert_eq!(s5_sleep_value(PM1_CNT_SLP_TYP_MASK | PM1_CNT_SCI_EN) & PM1_CNT_SLP_TYP_MASK, 0);
+    }
+}
@@ -0,0 +1,322 @@
+//! QEMU Q35 Power Button Detection
+//!
+//! Polls the ICH9 PM1 status register for power button presses (e.g. the QEMU `system_powerdown` monitor command)
+//! while boot services are available and invokes a callback when one is detected. The default callback shuts the
+//! platform down through the [`PlatformReset`] service.
+//!
+//! The polling timer is owned by the [`PowerButtonMonitor`] service, which can stop polling and release the timer.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
+
+extern crate alloc;
+use alloc::boxed::Box;
+use core::{cell::Cell, ptr::NonNull};
+
+use patina::{
+    boot_services::{
+        BootServices, StandardBootServices,
+        event::{EventTimerType, EventType},
+        tpl::Tpl,
+    },
+    component::{
+        component,
+        params::{Commands, Config},
+        service::{IntoService, Service},
+    },
+    error::EfiError,
+};
+use patina_mm::config::MmCommunicationConfiguration;
+use r_efi::efi;
+use x86_64::instructions::port::Port;
+
+use super::platform_reset::PlatformReset;
+use crate::q35::registers as register;
+
+/// Power button polling interval in 100 ns units (100 ms).
+const POLL_INTERVAL: u64 = 1_000_000;
+
+/// Stops the power button polling started by [`QemuQ35PowerButton`].
+pub trait PowerButtonMonitor {
+    /// Closes the polling timer event and releases its context. Does nothing if polling already stopped.
+    fn stop(&self) -> patina::error::Result<()>;
+}
+
+/// Access to the PM1 status and enable registers, abstracted so the power button handling can be tested without
+/// hardware.
+trait Pm1Registers {
+    /// Reads PM1_STS.
+    fn read_status(&self) -> u16;
+    /// Writes `value` to PM1_STS. Status bits are write-one-to-clear.
+    fn write_status(&self, value: u16);
+    /// Reads PM1_EN.
+    fn read_enable(&self) -> u16;
+    /// Writes `value` to PM1_EN.
+    fn write_enable(&self, value: u16);
+}
+
+/// The PM1 registers in the ACPI I/O register block at `pm_base`.
+struct Pm1IoPorts {
+    pm_base: u16,
+}
+
+impl Pm1Registers for Pm1IoPorts {
+    fn read_status(&self) -> u16 {
+        // SAFETY: PM1_STS is a fixed offset from PMBASE and reading it has no side effects.
+        unsafe { Port::<u16>::new(self.pm_base + register::ich9::PM1_STS).read() }
+    }
+
+    fn write_status(&self, value: u16) {
+        // SAFETY: PM1_STS is a fixed offset from PMBASE. Only the status bits set in `value` are cleared.
+        unsafe { Port::<u16>::new(self.pm_base + register::ich9::PM1_STS).write(value) }
+    }
+
+    fn read_enable(&self) -> u16 {
+        // SAFETY: PM1_EN is a fixed offset from PMBASE and reading it has no side effects.
+        unsafe { Port::<u16>::new(self.pm_base + register::ich9::PM1_EN).read() }
+    }
+
+    fn write_enable(&self, value: u16) {
+        // SAFETY: PM1_EN is a fixed offset from PMBASE.
+        unsafe { Port::<u16>::new(self.pm_base + register::ich9::PM1_EN).write(value) }
+    }
+}
+
+/// State shared with the polling timer event.
+struct PowerButtonContext {
+    pm_base: u16,
+    callback: fn(&dyn PlatformReset),
+    reset: Service<dyn PlatformReset>,
+}
+
+/// Owner of the polling timer event and the context it was created with.
+#[derive(IntoService)]
+#[service(dyn PowerButtonMonitor)]
+struct PowerButtonPoller {
+    boot_services: StandardBootServices,
+    poll: Cell<Option<(efi::Event, NonNull<PowerButtonContext>)>>,
+}
+
+/// The QEMU Q35 power button component.
+pub struct QemuQ35PowerButton {
+    callback: fn(&dyn PlatformReset),
+}
+
+impl Default for QemuQ35PowerButton {
+    fn default() -> Self {
+        Self { callback: shutdown }
+    }
+}
+
+#[component]
+impl QemuQ35PowerButton {
+    /// Creates a new instance of the QEMU Q35 power button component that shuts down the platform on a press.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the callback invoked with the [`PlatformReset`] service when a power button press is detected.
+    pub fn with_callback(mut self, callback: fn(&dyn PlatformReset)) -> Self {
+        self.callback = callback;
+        self
+    }
+
+    /// Entry point for the QEMU Q35 power button component.
+    ///
+    /// Depends on the locked `MmCommunicationConfiguration` for the ACPI (PMBASE) I/O port.
+    fn entry_point(
+        self,
+        config: Config<MmCommunicationConfiguration>,
+        reset: Service<dyn PlatformReset>,
+        boot_services: StandardBootServices,
+        mut commands: Commands,
+    ) -> patina::error::Result<()> {
+        log::debug!("Power Button Entry Point");
+
+        let pm_base = config.acpi_base.get_io_value();
+        let pm1 = Pm1IoPorts { pm_base };
+
+        // Discard any press latched before the firmware started watching for one, then let presses latch the status.
+        clear_power_button_status(&pm1);
+        enable_power_button(&pm1);
+
+        let context =
+            NonNull::from(Box::leak(Box::new(PowerButtonContext { pm_base, callback: self.callback, reset })));
+        let poller = PowerButtonPoller { boot_services, poll: Cell::new(None) };
+
+        let event = match poller.boot_services.create_event(
+            EventType::TIMER | EventType::NOTIFY_SIGNAL,
+            Tpl::CALLBACK,
+            Some(poll_power_button),
+            context.as_ptr(),
+        ) {
+            Ok(event) => event,
+            Err(status) => {
+                // SAFETY: No event references the context, so it is released here.
+                drop(unsafe { Box::from_raw(context.as_ptr()) });
+                return Err(EfiError::from(status));
+            }
+        };
+        poller.poll.set(Some((event, context)));
+
+        if let Err(status) = poller.boot_services.set_timer(event, EventTimerType::Periodic, POLL_INTERVAL) {
+            poller.stop()?;
+            return Err(EfiError::from(status));
+        }
+
+        commands.add_service(poller);
+
+        Ok(())
+    }
+}
+
+impl PowerButtonMonitor for PowerButtonPoller {
+    fn stop(&self) -> patina::error::Result<()> {
+        let Some((event, context)) = self.poll.take() else {
+            return Ok(());
+        };
+
+        if let Err(status) = self.boot_services.close_event(event) {
+            self.poll.set(Some((event, context)));
+            return Err(EfiError::from(status));
+        }
+
+        // SAFETY: The context was leaked when polling started and the only event that referenced it is closed.
+        drop(unsafe { Box::from_raw(context.as_ptr()) });
+        log::debug!("Power button polling stopped");
+
+        Ok(())
+    }
+}
+
+/// Returns whether the power button status bit is set in the PM1 status register value `pm1_sts`.
+pub fn is_power_button_pressed(pm1_sts: u16) -> bool {
+    pm1_sts & register::ich9::PWRBTN_STS != 0
+}
+
+extern "efiapi" fn poll_power_button(_event: efi::Event, context: *mut PowerButtonContext) {
+    // SAFETY: The context outlives the event; it is only released after the event is closed.
+    let Some(context) = (unsafe { context.as_ref() }) else {
+        return;
+    };
+
+    if acknowledge_power_button(&Pm1IoPorts { pm_base: context.pm_base }) {
+        log::info!("Power button pressed");
+        (context.callback)(*context.reset);
+    }
+}
+
+/// Returns whether the power button was pressed, clearing the power button status if it was.
+fn acknowledge_power_button(pm1: &impl Pm1Registers) -> bool {
+    if !is_power_button_pressed(pm1.read_status()) {
+        return false;
+    }
+    clear_power_button_status(pm1);
+    true
+}
+
+fn clear_power_button_status(pm1: &impl Pm1Registers) {
+    // PM1_STS bits are write-one-to-clear, so only the power button status is cleared.
+    pm1.write_status(register::ich9::PWRBTN_STS);
+}
+
+fn enable_power_button(pm1: &impl Pm1Registers) {
+    pm1.write_enable(pm1.read_enable() | register::ich9::PWRBTN_EN);
+}
+
+/// Shuts the platform down through the [`PlatformReset`] service.
+fn shutdown(reset: &dyn PlatformReset) {
+    reset.shutdown();
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    use super::*;
+    use register::ich9::{PWRBTN_EN, PWRBTN_STS};
+
+    /// PM1_STS timer overflow status, used as an unrelated status bit.
+    const TIMER_OVERFLOW_STS: u16 = 0x0001;
+    /// PM1_EN global enable, used as an unrelated enable bit.
+    const GLOBAL_EN: u16 = 0x0020;
+
+    /// PM1 registers with write-one-to-clear status bits that record every status write.
+    #[derive(Default)]
+    struct MockPm1 {
+        status: Cell<u16>,
+        enable: Cell<u16>,
+        status_writes: RefCell<Vec<u16>>,
+    }
+
+    impl Pm1Registers for MockPm1 {
+        fn read_status(&self) -> u16 {
+            self.status.get()
+        }
+
+        fn write_status(&self, value: u16) {
+            self.status_writes.borrow_mut().push(value);
+            self.status.set(self.status.get() & !value);
+        }
+
+        fn read_enable(&self) -> u16 {
+            self.enable.get()
+        }
+
+        fn write_enable(&self, value: u16) {
+            self.enable.set(value);
+        }
+    }
+
+    #[test]
+    fn power_button_press_is_detected() {
+        assert!(is_power_button_pressed(PWRBTN_STS));
+        assert!(is_power_button_pressed(PWRBTN_STS | 0x0001));
+    }
+
+    #[test]
+    fn other_status_bits_are_ignored() {
+        assert!(!is_power_button_pressed(0));
+        assert!(!is_power_button_pressed(!PWRBTN_STS));
+    }
+
+    #[test]
+    fn press_is_acknowledged_without_clearing_other_status() {
+        let pm1 = MockPm1 { status: Cell::new(PWRBTN_STS | TIMER_OVERFLOW_STS), ..Default::default() };
+
+        assert!(acknowledge_power_button(&pm1));
+        assert_eq!(pm1.status.get(), TIMER_OVERFLOW_STS);
+        assert_eq!(*pm1.status_writes.borrow(), [PWRBTN_STS]);
+    }
+
+    #[test]
+    fn press_is_reported_once() {
+        let pm1 = MockPm1 { status: Cell::new(PWRBTN_STS), ..Default::default() };
+
+        assert!(acknowledge_power_button(&pm1));
+        assert!(!acknowledge_power_button(&pm1));
+    }
+
+    #[test]
+    fn no_press_leaves_status_untouched() {
+        let pm1 = MockPm1 { status: Cell::new(TIMER_OVERFLOW_STS), ..Default::default() };
+
+        assert!(!acknowledge_power_button(&pm1));
+        assert_eq!(pm1.status.get(), TIMER_OVERFLOW_STS);
+        assert!(pm1.status_writes.borrow().is_empty());
+    }
+
+    #[test]
+    fn enabling_the_power_button_preserves_other_enables() {
+        let pm1 = MockPm1 { enable: Cell::new(GLOBAL_EN), ..Default::default() };
+
+        enable_power_button(&pm1);
+        assert_eq!(pm1.enable.get(), GLOBAL_EN | PWRBTN_EN);
+    }
+}
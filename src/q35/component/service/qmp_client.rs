@@ -0,0 +1,260 @@
+//! QEMU QMP Command Client
+//!
+//! Lets a host-side test harness drive the firmware with QMP-style commands without rebuilding the firmware binary.
+//! The harness injects a single JSON command as the fw_cfg file `opt/qmp_cmd`
+//! (e.g. `-fw_cfg name=opt/qmp_cmd,string={"execute":"quit"}`) and the command is executed once during dispatch.
+//!
+//! Supported commands:
+//!
+//! - `query-status`: reports that the firmware is running.
+//! - `quit`: exits QEMU through the `isa-debug-exit` device.
+//! - `human-monitor-command`: always rejected as the firmware has no monitor to forward the command line to.
+//!
+//! fw_cfg files injected from the command line are read-only to the guest, so the response that would be written to
+//! `opt/qmp_resp` is emitted on the debug log prefixed with `QMP response:` for the harness to collect.
+//!
+//! ## References
+//!
+//! - [QEMU Machine Protocol Specification](https://www.qemu.org/docs/master/interop/qmp-spec.html)
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
+
+extern crate alloc;
+use alloc::{format, string::String, vec::Vec};
+
+use patina::component::component;
+use qemu_exit::QEMUExit;
+
+use crate::q35::fw_cfg;
+
+/// fw_cfg file containing the host-injected command.
+pub const QMP_COMMAND_FILE: &str = "opt/qmp_cmd";
+/// fw_cfg file name the response is reported under.
+pub const QMP_RESPONSE_FILE: &str = "opt/qmp_resp";
+
+/// A QMP command understood by [`QemuQmpClient`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QmpCommand {
+    /// `query-status`
+    QueryStatus,
+    /// `quit`
+    Quit,
+    /// `human-monitor-command` with its `command-line` argument.
+    HumanMonitorCommand(String),
+    /// Any other command name.
+    Unknown(String),
+}
+
+impl QmpCommand {
+    /// Parses a QMP command object of the form `{"execute": "<name>", "arguments": {...}}`.
+    ///
+    /// Returns `None` if the input does not contain an `execute` member with a string value.
+    pub fn parse(json: &str) -> Option<Self> {
+        let name = string_member(json, &["execute"])?;
+        Some(match name {
+            "query-status" => Self::QueryStatus,
+            "quit" => Self::Quit,
+            "human-monitor-command" => Self::HumanMonitorCommand(String::from(
+                string_member(json, &["arguments", "command-line"]).unwrap_or_default(),
+            )),
+            other => Self::Unknown(String::from(other)),
+        })
+    }
+}
+
+/// Returns the string value of the member at `path` in `json`, e.g. `["arguments", "command-line"]`.
+///
+/// The first element of `path` names a member of the top-level object and each following element names a member of
+/// the object value of the previous one. Members with the same name elsewhere in the document are never matched, and
+/// neither are string values that happen to equal a member name. Escape sequences within strings are not supported.
+fn string_member<'a>(json: &'a str, path: &[&str]) -> Option<&'a str> {
+    let (name, parents) = path.split_last()?;
+    // Each enclosing container, whether it is an object, and the member name it is the value of.
+    let mut containers: Vec<(bool, Option<&str>)> = Vec::new();
+    let mut member: Option<&str> = None;
+    let mut expect_key = false;
+    let mut index = 0;
+
+    while index < json.len() {
+        match json.as_bytes()[index] {
+            b'{' => {
+                containers.push((true, member.take()));
+                expect_key = true;
+            }
+            b'[' => {
+                containers.push((false, member.take()));
+                expect_key = false;
+            }
+            b'}' | b']' => {
+                containers.pop();
+                member = None;
+                expect_key = false;
+            }
+            b',' => {
+                member = None;
+                expect_key = matches!(containers.last(), Some((true, _)));
+            }
+            b'"' => {
+                let start = index + 1;
+                let end = start + json[start..].find('"')?;
+                if expect_key {
+                    let key = &json[start..end];
+                    if key == *name && is_at_path(&containers, parents) {
+                        let value = json[end + 1..].trim_start().strip_prefix(':')?.trim_start().strip_prefix('"')?;
+                        return Some(&value[..value.find('"')?]);
+                    }
+                    member = Some(key);
+                }
+                expect_key = false;
+                index = end;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+
+    None
+}
+
+/// Returns whether the innermost of `containers` is the object reached from the top-level object through `parents`.
+fn is_at_path(containers: &[(bool, Option<&str>)], parents: &[&str]) -> bool {
+    let Some(((true, _), nested)) = containers.split_first() else {
+        return false;
+    };
+    nested.len() == parents.len()
+        && nested.iter().zip(parents).all(|((is_object, member), parent)| *is_object && *member == Some(*parent))
+}
+
+/// The QEMU QMP command client component.
+#[derive(Default)]
+pub struct QemuQmpClient;
+
+#[component]
+impl QemuQmpClient {
+    /// Creates a new instance of the QEMU QMP command client component.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Entry point for the QEMU QMP command client component.
+    ///
+    /// Does nothing if the host did not inject a command.
+    fn entry_point(self) -> patina::error::Result<()> {
+        log::debug!("QMP Client Entry Point");
+
+        let Some(file) = fw_cfg::find_file(QMP_COMMAND_FILE) else {
+            log::debug!("No {QMP_COMMAND_FILE} fw_cfg file present");
+            return Ok(());
+        };
+
+        let contents = fw_cfg::read_file(&file);
+        let Some(command) = core::str::from_utf8(&contents).ok().and_then(QmpCommand::parse) else {
+            log::error!("Failed to parse QMP command from {QMP_COMMAND_FILE}");
+            respond(r#"{"error": {"class": "GenericError", "desc": "Invalid QMP command"}}"#);
+            return Ok(());
+        };
+
+        log::info!("Executing QMP command {command:?}");
+        match command {
+            QmpCommand::QueryStatus => respond(r#"{"return": {"status": "running", "running": true}}"#),
+            QmpCommand::Quit => {
+                respond(r#"{"return": {}}"#);
+                // SAFETY: The QEMU command line used with this firmware (in patina-qemu) configures
+                // `isa-debug-exit,iobase=0xf4,iosize=0x04`.
+                unsafe { qemu_exit::X86::new(0xf4, 0x1) }.exit_success();
+            }
+            QmpCommand::HumanMonitorCommand(command_line) => respond(&format!(
+                r#"{{"error": {{"class": "GenericError", "desc": "No monitor available for '{command_line}'"}}}}"#
+            )),
+            QmpCommand::Unknown(name) => respond(&format!(
+                r#"{{"error": {{"class": "CommandNotFound", "desc": "The command {name} has not been found"}}}}"#
+            )),
+        }
+
+        Ok(())
+    }
+}
+
+/// Reports `response` for the host-side harness.
+fn respond(response: &str) {
+    log::info!("QMP response: ({QMP_RESPONSE_FILE}) {response}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_commands_are_parsed() {
+        assert_eq!(QmpCommand::parse(r#"{"execute": "query-status"}"#), Some(QmpCommand::QueryStatus));
+        assert_eq!(QmpCommand::parse(r#"{"execute":"quit"}"#), Some(QmpCommand::Quit));
+    }
+
+    #[test]
+    fn human_monitor_command_line_is_parsed() {
+        assert_eq!(
+            QmpCommand::parse(r#"{"execute": "human-monitor-command", "arguments": {"command-line": "info cpus"}}"#),
+            Some(QmpCommand::HumanMonitorCommand(String::from("info cpus")))
+        );
+        assert_eq!(
+            QmpCommand::parse(r#"{"execute": "human-monitor-command"}"#),
+            Some(QmpCommand::HumanMonitorCommand(String::new()))
+        );
+    }
+
+    #[test]
+    fn unknown_commands_are_passed_through() {
+        assert_eq!(QmpCommand::parse(r#"{"execute": "stop"}"#), Some(QmpCommand::Unknown(String::from("stop"))));
+    }
+
+    #[test]
+    fn keys_inside_string_values_are_ignored() {
+        assert_eq!(QmpCommand::parse(r#"{"id": "execute", "execute": "query-status"}"#), Some(QmpCommand::QueryStatus));
+        assert_eq!(QmpCommand::parse(r#"{"id": "execute"}"#), None);
+    }
+
+    #[test]
+    fn nested_members_are_not_top_level_members() {
+        assert_eq!(QmpCommand::parse(r#"{"id": "execute", "arguments": {"execute": "quit"}}"#), None);
+        assert_eq!(
+            QmpCommand::parse(r#"{"arguments": {"execute": "quit"}, "execute": "stop"}"#),
+            Some(QmpCommand::Unknown(String::from("stop")))
+        );
+    }
+
+    #[test]
+    fn command_line_is_only_read_from_arguments() {
+        assert_eq!(
+            QmpCommand::parse(r#"{"execute": "human-monitor-command", "command-line": "info cpus"}"#),
+            Some(QmpCommand::HumanMonitorCommand(String::new()))
+        );
+        assert_eq!(
+            QmpCommand::parse(
+                r#"{"execute": "human-monitor-command", "arguments": {"opts": {"command-line": "quit"}, "command-line": "info cpus"}}"#
+            ),
+            Some(QmpCommand::HumanMonitorCommand(String::from("info cpus")))
+        );
+    }
+
+    #[test]
+    fn string_elements_in_arrays_are_not_keys() {
+        assert_eq!(
+            QmpCommand::parse(r#"{"tags": ["execute", "quit"], "execute": "stop"}"#),
+            Some(QmpCommand::Unknown(String::from("stop")))
+        );
+    }
+
+    #[test]
+    fn invalid_commands_are_rejected() {
+        assert_eq!(QmpCommand::parse(""), None);
+        assert_eq!(QmpCommand::parse(r#"{"arguments": {}}"#), None);
+        assert_eq!(QmpCommand::parse(r#"{"execute": 1}"#), None);
+        assert_eq!(QmpCommand::parse(r#"{"execute": "quit"#), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! QEMU Q35 S3 Resume Support
+//!
+//! Detects whether the QEMU Q35 platform is capable of S3 (Suspend-to-RAM) resume and records the register state
+//! that must be restored on the S3 resume path.
+//!
+//! The boot script is exposed through the [`S3SaveState`] service; the PI `EFI_S3_SAVE_STATE_PROTOCOL` is not
+//! installed.
+//!
+//! ## References
+//!
+//! - [FADT Table Definition](https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#fixed-acpi-description-table-fadt)
+//! - [PI Specification Volume 5 - S3 Resume Boot Script](https://uefi.org/specs/PI/1.9/V5_S3_Resume.html)
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
+
+extern crate alloc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use patina::component::{
+    component,
+    params::{Commands, Config},
+    service::{IntoService, Service},
+};
+use patina_acpi::service::AcpiTableManager;
+use patina_mm::config::MmCommunicationConfiguration;
+use r_efi::efi;
+use x86_64::instructions::port::Port;
+use zerocopy::{Immutable, IntoBytes};
+
+use super::smm_verify::SmmModeVerified;
+use crate::q35::{fw_cfg, registers as register};
+
+/// FADT signature ('FACP').
+const FADT_SIGNATURE: u32 = patina::signature!('F', 'A', 'C', 'P');
+
+/// FADT `Flags` bit indicating that the `WBINVD` instruction correctly flushes processor caches.
+const FADT_FLAGS_WBINVD: u32 = 0x01;
+
+/// Boot script opcode for an I/O write (`EFI_BOOT_SCRIPT_IO_WRITE_OPCODE`).
+const BOOT_SCRIPT_IO_WRITE_OPCODE: u16 = 0x00;
+
+/// Boot script width for 32-bit accesses (`EfiBootScriptWidthUint32`).
+const BOOT_SCRIPT_WIDTH_UINT32: u32 = 0x02;
+
+/// Size of an encoded [`BootScriptIoWrite32`] entry: the 19-byte `EFI_BOOT_SCRIPT_IO_WRITE` header and one 32-bit value.
+const BOOT_SCRIPT_IO_WRITE32_LENGTH: usize = 23;
+
+/// Set once the FADT has been installed and reports the capabilities required for S3.
+static S3_CAPABLE: AtomicBool = AtomicBool::new(false);
+
+/// Saves the platform state that must be restored on the S3 resume path.
+pub trait S3SaveState {
+    /// Returns whether the installed FADT reports the capabilities required for S3 resume.
+    fn is_s3_capable(&self) -> bool;
+
+    /// Returns the boot script entries that must be replayed on the S3 resume path.
+    fn boot_script(&self) -> &[BootScriptIoWrite32];
+}
+
+/// A single 32-bit I/O write boot script entry.
+///
+/// Matches the packed `EFI_BOOT_SCRIPT_IO_WRITE` layout (`OpCode`, `Length`, `Width`, `Count`, `Address`) followed by a
+/// single 32-bit data value.
+#[derive(Clone, Copy, Debug, IntoBytes, Immutable)]
+#[repr(C, packed)]
+pub struct BootScriptIoWrite32 {
+    op_code: u16,
+    length: u8,
+    width: u32,
+    count: u32,
+    address: u64,
+    value: u32,
+}
+
+impl BootScriptIoWrite32 {
+    /// Creates a boot script entry that writes `value` to the I/O port `port` on S3 resume.
+    pub fn new(port: u16, value: u32) -> Self {
+        Self {
+            op_code: BOOT_SCRIPT_IO_WRITE_OPCODE,
+            length: core::mem::size_of::<Self>() as u8,
+            width: BOOT_SCRIPT_WIDTH_UINT32,
+            count: 1,
+            address: port as u64,
+            value,
+        }
+    }
+
+    /// Returns the encoded boot script entry.
+    pub fn encode(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+const _: () = assert!(core::mem::size_of::<BootScriptIoWrite32>() == BOOT_SCRIPT_IO_WRITE32_LENGTH);
+
+/// The QEMU Q35 S3 resume support component.
+///
+/// Records the ICH9 SMI_EN register value as a boot script entry and checks the FADT once it is installed to
+/// determine whether the platform can support S3. Publishes itself as the [`S3SaveState`] service.
+#[derive(IntoService, Default)]
+#[service(dyn S3SaveState)]
+pub struct QemuQ35S3Support {
+    boot_script: Vec<BootScriptIoWrite32>,
+}
+
+#[component]
+impl QemuQ35S3Support {
+    /// Creates a new instance of the QEMU Q35 S3 resume support component.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Entry point for the QEMU Q35 S3 resume support component.
+    ///
+    /// Depends on the locked `MmCommunicationConfiguration` for the ACPI (PMBASE) I/O port. Only dispatched once
+    /// [`SmmModeVerifier`](super::smm_verify::SmmModeVerifier) has confirmed that software SMIs are handled, so the
+    /// recorded SMI_EN value includes the enables set by the platform MM control initialization.
+    fn entry_point(
+        mut self,
+        config: Config<MmCommunicationConfiguration>,
+        _smm_verified: Config<SmmModeVerified>,
+        acpi: Service<AcpiTableManager>,
+        mut commands: Commands,
+    ) -> patina::error::Result<()> {
+        log::debug!("S3 Resume Support Entry Point");
+
+        let pm_base = config.acpi_base.get_io_value();
+
+        let smi_en_port_address = pm_base + register::ich9::PMBASE_OFS_SMI_EN as u16;
+        let mut smi_en_port: Port<u32> = Port::new(smi_en_port_address);
+        let smi_enable_val = unsafe { smi_en_port.read() };
+        self.boot_script.push(BootScriptIoWrite32::new(smi_en_port_address, smi_enable_val));
+        log::debug!("S3 boot script: SMI_EN ({smi_en_port_address:#X}) = {smi_enable_val:#X}");
+
+        acpi.register_notify(|header, _, _| {
+            let (signature, length) = (header.signature, header.length);
+            if signature != FADT_SIGNATURE {
+                return efi::Status::SUCCESS;
+            }
+
+            // SAFETY: The header is the start of the installed FADT, which is `length` bytes long.
+            let fadt = unsafe { core::slice::from_raw_parts(header as *const _ as *const u8, length as usize) };
+            let Some(flags) = fw_cfg::fadt_flags(fadt) else {
+                log::warn!("FADT is too short to contain the Flags field - S3 resume not supported");
+                return efi::Status::SUCCESS;
+            };
+
+            let s3_capable = is_s3_capable(flags);
+            S3_CAPABLE.store(s3_capable, Ordering::Release);
+            log::info!("FADT Flags: {flags:#X} - S3 resume {}", if s3_capable { "supported" } else { "not supported" });
+
+            efi::Status::SUCCESS
+        })
+        .map_err(|e| {
+            log::error!("Failed to register FADT notification: {e:?}");
+            patina::error::EfiError::Unsupported
+        })?;
+
+        commands.add_service(self);
+
+        Ok(())
+    }
+}
+
+impl S3SaveState for QemuQ35S3Support {
+    fn is_s3_capable(&self) -> bool {
+        S3_CAPABLE.load(Ordering::Acquire)
+    }
+
+    fn boot_script(&self) -> &[BootScriptIoWrite32] {
+        &self.boot_script
+    }
+}
+
+/// Returns whether S3 resume is supported given the FADT `fadt_flags`.
+///
+/// SCI_EN is not considered: with SMM enabled it stays clear until the OS issues `ACPI_ENABLE`.
+fn is_s3_capable(fadt_flags: u32) -> bool {
+    fadt_flags & FADT_FLAGS_WBINVD != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_write_entry_matches_boot_script_layout() {
+        let entry = BootScriptIoWrite32::new(0x630, 0x1234_5678);
+        let bytes = entry.encode();
+
+        assert_eq!(bytes.len(), BOOT_SCRIPT_IO_WRITE32_LENGTH);
+        assert_eq!(&bytes[0..2], &BOOT_SCRIPT_IO_WRITE_OPCODE.to_le_bytes());
+        assert_eq!(bytes[2], BOOT_SCRIPT_IO_WRITE32_LENGTH as u8);
+        assert_eq!(&bytes[3..7], &BOOT_SCRIPT_WIDTH_UINT32.to_le_bytes());
+        assert_eq!(&bytes[7..11], &1u32.to_le_bytes());
+        assert_eq!(&bytes[11..19], &0x630u64.to_le_bytes());
+        assert_eq!(&bytes[19..23], &0x1234_5678u32.to_le_bytes());
+    }
+
+    #[test]
+    fn io_write_entry_encodes_full_port_range() {
+        let bytes = BootScriptIoWrite32::new(u16::MAX, u32::MAX).encode().to_vec();
+        assert_eq!(&bytes[11..19], &0xFFFFu64.to_le_bytes());
+        assert_eq!(&bytes[19..23], &[0xFF; 4]);
+    }
+
+    #[test]
+    fn s3_requires_wbinvd() {
+        assert!(is_s3_capable(FADT_FLAGS_WBINVD));
+        assert!(is_s3_capable(u32::MAX));
+        assert!(!is_s3_capable(0));
+        assert!(!is_s3_capable(!FADT_FLAGS_WBINVD));
+    }
+}
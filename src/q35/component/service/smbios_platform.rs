@@ -3,7 +3,7 @@
 //! Platform component that populates and publishes SMBIOS tables:
 //! 1. Uses the type-safe `add_record<T>()` API for adding SMBIOS records
 //! 2. Publishes the table after all records are added
-//! 3. Uses structured record types (Type0, Type1)
+//! 3. Uses structured record types (Type0, Type1, Type2, Type3, Type4, Type16, Type17, Type19)
 //!
 //! ## License
 //!
@@ -13,22 +13,47 @@
 //!
 
 extern crate alloc;
-use alloc::{string::String, vec};
+use alloc::{format, string::String, vec, vec::Vec};
 
+#[cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+use crate::q35::cpuid_to_smbios::{CpuidProcessorInfo, processor_family_byte};
 use patina::{
-    component::{component, service::Service},
+    boot_services::{BootServices, StandardBootServices},
+    component::{component, params::Handle, service::Service},
     error::Result,
+    pi::hob::{self, Hob, HobList, ResourceDescriptor},
 };
 use patina_smbios::{
     service::{SMBIOS_HANDLE_PI_RESERVED, Smbios, SmbiosExt, SmbiosTableHeader},
     smbios_record::{
         Type0PlatformFirmwareInformation, Type1SystemInformation, Type2BaseboardInformation, Type3SystemEnclosure,
+        Type16PhysicalMemoryArray, Type17MemoryDevice, Type19MemoryArrayMappedAddress,
     },
     smbios_types::{
         BiosCharacteristics, BiosCharacteristicsExt1, BiosCharacteristicsExt2, BoardType, BootUpState,
-        ExtendedBiosRomSize, FeatureFlags, PowerSupplyState, SecurityStatus, ThermalState, WakeUpType,
+        ExtendedBiosRomSize, FeatureFlags, MemoryArrayErrorCorrectionType, MemoryArrayLocation, MemoryArrayUse,
+        MemoryCapability, MemoryDeviceAttributes, MemoryDeviceTechnology, MemoryDeviceType, MemoryDeviceTypeDetails,
+        MemoryFormFactor, PowerSupplyState, SecurityStatus, ThermalState, WakeUpType,
     },
 };
+#[cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+use patina_smbios::{
+    smbios_record::Type4ProcessorInformation,
+    smbios_types::{
+        ProcessorCharacteristics, ProcessorInformationStatus, ProcessorTypeData, ProcessorUpgrade, ProcessorVoltage,
+    },
+};
+
+/// Type 16 maximum capacity value indicating that the extended maximum capacity field is used.
+const MAXIMUM_CAPACITY_EXTENDED: u32 = 0x8000_0000;
+/// Type 17 size value indicating that the extended size field is used.
+const MEMORY_DEVICE_SIZE_EXTENDED: u16 = 0x7FFF;
+/// Type 17 size granularity bit indicating that the size is in kilobytes rather than megabytes.
+const MEMORY_DEVICE_SIZE_KB: u16 = 0x8000;
+/// Type 19 address value indicating that the extended address fields are used.
+const MAPPED_ADDRESS_EXTENDED: u32 = 0xFFFF_FFFF;
+/// Handle value indicating that no memory error information structure is provided.
+const NO_MEMORY_ERROR_INFORMATION: u16 = 0xFFFE;
 
 /// Q35 platform SMBIOS component that populates and publishes SMBIOS tables.
 ///
@@ -45,7 +70,12 @@ impl Q35SmbiosPlatform {
         Self
     }
 
-    fn entry_point(self, smbios: Service<dyn Smbios>) -> Result<()> {
+    fn entry_point(
+        self,
+        smbios: Service<dyn Smbios>,
+        boot_services: StandardBootServices,
+        image_handle: Handle,
+    ) -> Result<()> {
         log::debug!("=== Q35 SMBIOS Platform Component ===");
 
         // Verify SMBIOS version
@@ -175,6 +205,20 @@ impl Q35SmbiosPlatform {
             Err(e) => log::warn!("  Failed to add Type 2: {:?}", e),
         }
 
+        #[cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+        if let Err(e) = self.populate_processor_info(&smbios) {
+            log::warn!("  Failed to add Type 4: {:?}", e);
+        }
+
+        match relocated_hob_list(&boot_services, *image_handle) {
+            Some(hob_list) => {
+                if let Err(e) = self.populate_memory_info(&hob_list, &smbios) {
+                    log::warn!("  Failed to add memory information: {:?}", e);
+                }
+            }
+            None => log::warn!("  HOB list configuration table not found, skipping memory information"),
+        }
+
         // Type 127 End-of-Table marker is automatically added by the manager during initialization
         log::trace!("Platform SMBIOS records created successfully");
 
@@ -197,4 +241,306 @@ impl Q35SmbiosPlatform {
         log::debug!("SMBIOS platform component initialized successfully");
         Ok(())
     }
+
+    /// Adds a Type 4 (Processor Information) record populated from CPUID on the boot processor.
+    #[cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+    pub fn populate_processor_info(&self, smbios: &Service<dyn Smbios>) -> Result<()> {
+        let cpu = CpuidProcessorInfo::read();
+        log::trace!(
+            "CPUID: Family {:#X}, Model {:#X}, Stepping {:#X}, Base {} MHz, Max {} MHz, Bus {} MHz",
+            cpu.family,
+            cpu.model,
+            cpu.stepping,
+            cpu.base_mhz,
+            cpu.max_mhz,
+            cpu.bus_mhz
+        );
+
+        let processor_family2 = cpu.processor_family();
+        let processor_info = Type4ProcessorInformation {
+            header: SmbiosTableHeader::new(4, 0, SMBIOS_HANDLE_PI_RESERVED),
+            socket_designation: 1,
+            processor_type: ProcessorTypeData::CentralProcessor,
+            processor_family: processor_family_byte(processor_family2),
+            processor_manufacturer: 2,
+            processor_id: cpu.processor_id(),
+            processor_version: 3,
+            voltage: ProcessorVoltage::new(),
+            external_clock: cpu.bus_mhz,
+            max_speed: cpu.max_mhz,
+            current_speed: cpu.base_mhz,
+            status: ProcessorInformationStatus::new().with_cpu_status(1).with_cpu_socket_populated(true),
+            processor_upgrade: ProcessorUpgrade::Other,
+            l1_cache_handle: 0xFFFF,
+            l2_cache_handle: 0xFFFF,
+            l3_cache_handle: 0xFFFF,
+            serial_number: 0,
+            asset_tag: 0,
+            part_number: 0,
+            core_count: 0,
+            core_enabled: 0,
+            thread_count: cpu.logical_processors,
+            processor_characteristics: ProcessorCharacteristics::new()
+                .with_capable_64bit(cpu.long_mode)
+                .with_execute_protection(cpu.execute_disable),
+            processor_family2,
+            core_count2: 0,
+            core_enabled2: 0,
+            thread_count2: cpu.logical_processors as u16,
+            string_pool: vec![
+                String::from("CPU 0"),
+                cpu.manufacturer(),
+                cpu.brand.clone().unwrap_or_else(|| String::from("Unknown")),
+            ],
+        };
+
+        let handle = smbios.add_record(None, &processor_info).map_err(|e| {
+            log::error!("Failed to add Type 4 record: {e:?}");
+            patina::error::EfiError::DeviceError
+        })?;
+        log::trace!("  Type 4 (Processor Info) - Handle 0x{:04X}", handle);
+
+        Ok(())
+    }
+
+    /// Adds a Type 16 (Physical Memory Array) record covering all system memory described in `hob_list`, with one
+    /// Type 17 (Memory Device) record and one Type 19 (Memory Array Mapped Address) record for the size and base
+    /// address of each system memory resource descriptor.
+    pub fn populate_memory_info(&self, hob_list: &HobList, smbios: &Service<dyn Smbios>) -> Result<()> {
+        let regions = system_memory_regions(hob_list);
+        if regions.is_empty() {
+            log::error!("No system memory resource descriptor HOBs found");
+            return Err(patina::error::EfiError::NotFound);
+        }
+
+        let total_size: u64 = regions.iter().map(|(_, length)| length).sum();
+        let total_kb = total_size / 1024;
+        let memory_array = Type16PhysicalMemoryArray {
+            header: SmbiosTableHeader::new(16, 0, SMBIOS_HANDLE_PI_RESERVED),
+            location: MemoryArrayLocation::SystemBoard,
+            use_field: MemoryArrayUse::SystemMemory,
+            memory_error_correction: MemoryArrayErrorCorrectionType::NoEcc,
+            maximum_capacity: u32::try_from(total_kb)
+                .ok()
+                .filter(|&kb| kb < MAXIMUM_CAPACITY_EXTENDED)
+                .unwrap_or(MAXIMUM_CAPACITY_EXTENDED),
+            memory_error_information_handle: NO_MEMORY_ERROR_INFORMATION,
+            number_of_memory_devices: regions.len() as u16,
+            extended_maximum_capacity: total_size,
+            string_pool: vec![],
+        };
+
+        let array_handle = smbios.add_record(None, &memory_array).map_err(|e| {
+            log::error!("Failed to add Type 16 record: {e:?}");
+            patina::error::EfiError::DeviceError
+        })?;
+        log::trace!("  Type 16 (Physical Memory Array) - Handle 0x{:04X}", array_handle);
+
+        for (index, (base, length)) in regions.iter().enumerate() {
+            let (size, extended_size) = memory_device_size(*length);
+            let memory_device = Type17MemoryDevice {
+                header: SmbiosTableHeader::new(17, 0, SMBIOS_HANDLE_PI_RESERVED),
+                physical_memory_array_handle: array_handle,
+                memory_error_information_handle: NO_MEMORY_ERROR_INFORMATION,
+                total_width: 0xFFFF,
+                data_width: 0xFFFF,
+                size,
+                form_factor: MemoryFormFactor::Other,
+                device_set: 0,
+                device_locator: 1,
+                bank_locator: 2,
+                memory_type: MemoryDeviceType::Ram,
+                type_detail: MemoryDeviceTypeDetails::new().with_unknown(true),
+                speed: 0,
+                manufacturer: 3,
+                serial_number: 0,
+                asset_tag: 0,
+                part_number: 0,
+                attributes: MemoryDeviceAttributes::new(),
+                extended_size,
+                configured_memory_clock_speed: 0,
+                minimum_voltage: 0,
+                maximum_voltage: 0,
+                configured_voltage: 0,
+                memory_technology: MemoryDeviceTechnology::Dram,
+                memory_operating_mode_capability: MemoryCapability::new().with_volatile_memory(true),
+                firmware_version: 0,
+                module_manufacturer_id: 0,
+                module_product_id: 0,
+                memory_subsystem_controller_manufacturer_id: 0,
+                memory_subsystem_controller_product_id: 0,
+                non_volatile_size: 0,
+                volatile_size: *length,
+                cache_size: 0,
+                logical_size: 0,
+                extended_speed: 0,
+                extended_configured_memory_speed: 0,
+                pmic0_manufacturer_id: 0,
+                pmic0_revision_number: 0,
+                rcd_manufacturer_id: 0,
+                rcd_revision_number: 0,
+                string_pool: vec![format!("DIMM {index}"), format!("BANK {index}"), String::from("QEMU")],
+            };
+
+            match smbios.add_record(None, &memory_device) {
+                Ok(handle) => log::trace!("  Type 17 (Memory Device) - Handle 0x{:04X}", handle),
+                Err(e) => log::warn!("  Failed to add Type 17: {:?}", e),
+            }
+
+            let start_kb = base / 1024;
+            let end_kb = (base + length - 1) / 1024;
+            let (starting_address, ending_address) = match (u32::try_from(start_kb), u32::try_from(end_kb)) {
+                (Ok(start), Ok(end)) if end < MAPPED_ADDRESS_EXTENDED => (start, end),
+                _ => (MAPPED_ADDRESS_EXTENDED, MAPPED_ADDRESS_EXTENDED),
+            };
+            let mapped_address = Type19MemoryArrayMappedAddress {
+                header: SmbiosTableHeader::new(19, 0, SMBIOS_HANDLE_PI_RESERVED),
+                starting_address,
+                ending_address,
+                memory_array_handle: array_handle,
+                partition_width: 1,
+                extended_starting_address: *base,
+                extended_ending_address: base + length - 1,
+                string_pool: vec![],
+            };
+
+            match smbios.add_record(None, &mapped_address) {
+                Ok(handle) => log::trace!("  Type 19 (Memory Array Mapped Address) - Handle 0x{:04X}", handle),
+                Err(e) => log::warn!("  Failed to add Type 19: {:?}", e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the relocated HOB list that the DXE Core publishes in the HOB list configuration table.
+fn relocated_hob_list(
+    boot_services: &StandardBootServices,
+    image_handle: r_efi::efi::Handle,
+) -> Option<HobList<'static>> {
+    // SAFETY: The DXE Core image handle carries the loaded image protocol, which references the system table.
+    let loaded_image =
+        unsafe { boot_services.handle_protocol::<r_efi::efi::protocols::loaded_image::Protocol>(image_handle) }.ok()?;
+    // SAFETY: The system table and its configuration table array are owned by the DXE Core for the boot.
+    let tables = unsafe {
+        let system_table = loaded_image.system_table.as_ref()?;
+        core::slice::from_raw_parts(system_table.configuration_table, system_table.number_of_table_entries)
+    };
+    let table = tables.iter().find(|table| table.vendor_guid == patina::guids::HOB_LIST.into_inner())?;
+
+    let mut hob_list = HobList::new();
+    hob_list.discover_hobs(table.vendor_table);
+    Some(hob_list)
+}
+
+/// Returns the resource described by `hob` if it uses the resource descriptor version consumed by the DXE Core.
+///
+/// Pre-DXE phases may describe each region with both versions, so only one version is counted.
+fn resource_descriptor<'a>(hob: &'a Hob) -> Option<&'a ResourceDescriptor> {
+    match hob {
+        #[cfg(feature = "v1_resource_descriptor_support")]
+        Hob::ResourceDescriptor(resource) => Some(resource),
+        #[cfg(not(feature = "v1_resource_descriptor_support"))]
+        Hob::ResourceDescriptorV2(resource) => Some(&resource.v1),
+        _ => None,
+    }
+}
+
+/// Returns the base address and length of each non-empty system memory region described in `hob_list`.
+fn system_memory_regions(hob_list: &HobList) -> Vec<(u64, u64)> {
+    hob_list
+        .iter()
+        .filter_map(resource_descriptor)
+        .filter(|resource| resource.resource_type == hob::EFI_RESOURCE_SYSTEM_MEMORY && resource.resource_length != 0)
+        .map(|resource| (resource.physical_start, resource.resource_length))
+        .collect()
+}
+
+/// Returns the Type 17 size and extended size fields for a memory device of `length` bytes.
+///
+/// Devices smaller than 1 MB are reported with kilobyte granularity.
+fn memory_device_size(length: u64) -> (u16, u32) {
+    let size_mb = length / (1024 * 1024);
+    if size_mb == 0 {
+        return (MEMORY_DEVICE_SIZE_KB | (length / 1024) as u16, 0);
+    }
+
+    let size = u16::try_from(size_mb)
+        .ok()
+        .filter(|&mb| mb < MEMORY_DEVICE_SIZE_EXTENDED)
+        .unwrap_or(MEMORY_DEVICE_SIZE_EXTENDED);
+    (size, u32::try_from(size_mb).unwrap_or(u32::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patina::pi::hob::{ResourceDescriptorV2, header};
+
+    const MB: u64 = 1024 * 1024;
+
+    fn resource(physical_start: u64, resource_length: u64) -> ResourceDescriptor {
+        ResourceDescriptor {
+            header: header::Hob {
+                r#type: hob::RESOURCE_DESCRIPTOR,
+                length: core::mem::size_of::<ResourceDescriptor>() as u16,
+                reserved: 0,
+            },
+            owner: patina::BinaryGuid::from_string("00000000-0000-0000-0000-000000000000"),
+            resource_type: hob::EFI_RESOURCE_SYSTEM_MEMORY,
+            resource_attribute: 0,
+            physical_start,
+            resource_length,
+        }
+    }
+
+    fn resource_v2(physical_start: u64, resource_length: u64) -> ResourceDescriptorV2 {
+        let mut v1 = resource(physical_start, resource_length);
+        v1.header.length = core::mem::size_of::<ResourceDescriptorV2>() as u16;
+        ResourceDescriptorV2 { v1, attributes: 0 }
+    }
+
+    #[test]
+    fn two_memory_hobs_are_counted_once() {
+        let low = (resource(0, 2 * 1024 * MB), resource_v2(0, 2 * 1024 * MB));
+        let high = (resource(0x1_0000_0000, 512 * MB), resource_v2(0x1_0000_0000, 512 * MB));
+
+        let mut hob_list = HobList::new();
+        hob_list.push(Hob::ResourceDescriptor(&low.0));
+        hob_list.push(Hob::ResourceDescriptorV2(&low.1));
+        hob_list.push(Hob::ResourceDescriptor(&high.0));
+        hob_list.push(Hob::ResourceDescriptorV2(&high.1));
+
+        assert_eq!(system_memory_regions(&hob_list), vec![(0, 2 * 1024 * MB), (0x1_0000_0000, 512 * MB)]);
+    }
+
+    #[test]
+    fn non_memory_and_empty_hobs_are_skipped() {
+        let mut mmio = resource_v2(0xE000_0000, 256 * MB);
+        mmio.v1.resource_type = hob::EFI_RESOURCE_MEMORY_MAPPED_IO;
+        let empty = resource_v2(0x1000, 0);
+
+        let mut hob_list = HobList::new();
+        hob_list.push(Hob::ResourceDescriptorV2(&mmio));
+        hob_list.push(Hob::ResourceDescriptorV2(&empty));
+
+        assert!(system_memory_regions(&hob_list).is_empty());
+    }
+
+    #[test]
+    fn memory_device_size_uses_megabytes() {
+        assert_eq!(memory_device_size(2 * 1024 * MB), (2048, 2048));
+        assert_eq!(memory_device_size(MB), (1, 1));
+    }
+
+    #[test]
+    fn memory_device_size_uses_kilobytes_below_one_megabyte() {
+        assert_eq!(memory_device_size(640 * 1024), (MEMORY_DEVICE_SIZE_KB | 640, 0));
+    }
+
+    #[test]
+    fn memory_device_size_uses_extended_size() {
+        assert_eq!(memory_device_size(64 * 1024 * MB), (MEMORY_DEVICE_SIZE_EXTENDED, 64 * 1024));
+    }
 }
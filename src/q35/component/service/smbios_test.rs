@@ -38,7 +38,7 @@ fn test_c_protocol_layer(boot_services: &StandardBootServices) -> patina_test::e
 
     // Define the SMBIOS protocol GUID
     const SMBIOS_PROTOCOL_GUID: efi::Guid =
-        efi::Guid::from_fields(0x03583ff6, 0xcb36, 0x4940, 0x94, 0x7e, &[0xb9, 0xb3, 0x9f, 0x4a, 0xfa, 0xf7]);
+        patina::BinaryGuid::from_string("03583FF6-CB36-4940-947E-B9B39F4AFAF7").into_inner();
 
     // Locate the SMBIOS protocol
     let protocol_ptr = unsafe {
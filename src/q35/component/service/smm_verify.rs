@@ -0,0 +1,154 @@
+//! QEMU Q35 SMM Mode Verifier
+//!
+//! Verifies that the processor actually enters SMM when a software SMI is triggered. A request is placed in the MM
+//! communication buffer with a sentinel result value and the software SMI is triggered through the MM communication
+//! service. The MM Supervisor only overwrites the result if it handled the SMI.
+//!
+//! Components that depend on a functional MM environment should take a [`Config<SmmModeVerified>`] parameter so that
+//! they are only dispatched after verification succeeds.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina::component::{component, params::ConfigMut, service::Service};
+use patina_mm::service::MmCommunication;
+
+use super::mm_test::{MM_SUPERVISOR_REQUEST_HANDLER_GUID, MmSupervisorRequestHeader};
+
+/// Result value placed in the request before the SMI is triggered. The MM Supervisor never reports this value.
+const RESULT_SENTINEL: u64 = 0xA5A5_5A5A_A5A5_5A5A;
+
+/// Marker configuration locked once SMM mode has been verified.
+///
+/// Components that take a `Config<SmmModeVerified>` parameter are not dispatched if verification fails.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SmmModeVerified;
+
+/// QEMU Q35 SMM Mode Verifier Component
+#[derive(Default)]
+pub struct SmmModeVerifier;
+
+#[component]
+impl SmmModeVerifier {
+    /// Creates a new instance of the QEMU Q35 SMM mode verifier component.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Entry point for the QEMU Q35 SMM mode verifier component.
+    ///
+    /// Locks the `SmmModeVerified` configuration if the MM Supervisor handled the software SMI.
+    fn entry_point(
+        self,
+        mm_comm: Service<dyn MmCommunication>,
+        mut verified: ConfigMut<SmmModeVerified>,
+    ) -> patina::error::Result<()> {
+        log::debug!("SMM Mode Verifier Entry Point");
+
+        let request = MmSupervisorRequestHeader {
+            signature: u32::from_le_bytes([b'M', b'S', b'U', b'P']),
+            revision: 1,
+            request: 0x0003, // Request Version Info
+            reserved: 0,
+            result: RESULT_SENTINEL,
+        };
+
+        let response = unsafe {
+            mm_comm.communicate(
+                0,
+                core::slice::from_raw_parts(
+                    &request as *const _ as *const u8,
+                    core::mem::size_of::<MmSupervisorRequestHeader>(),
+                ),
+                patina::Guid::from(&MM_SUPERVISOR_REQUEST_HANDLER_GUID),
+            )
+        }
+        .map_err(|status| {
+            log::error!("SMM mode verification failed to trigger the software SMI: {status:?}");
+            patina::error::EfiError::DeviceError
+        })?;
+
+        Self::check_response(&response)?;
+
+        log::info!("SMM mode verified");
+        verified.lock();
+
+        Ok(())
+    }
+
+    /// Checks that the MM Supervisor updated the result of the request in `response`.
+    ///
+    /// ## Returns
+    ///
+    /// - `Ok(())` if the MM Supervisor handled the request.
+    /// - `Err(EfiError::DeviceError)` if the response is truncated or the result still holds the sentinel value.
+    ///
+    pub fn check_response(response: &[u8]) -> patina::error::Result<()> {
+        if response.len() < core::mem::size_of::<MmSupervisorRequestHeader>() {
+            log::error!("SMM mode verification response is truncated ({} bytes)", response.len());
+            return Err(patina::error::EfiError::DeviceError);
+        }
+
+        // SAFETY: The length check above ensures `response` holds a full header. The header only contains integer
+        // fields, so any byte pattern is valid, and `read_unaligned` places no alignment requirement on `response`.
+        let header = unsafe { core::ptr::read_unaligned(response.as_ptr() as *const MmSupervisorRequestHeader) };
+        let result = header.result;
+        if result == RESULT_SENTINEL {
+            log::error!("MM Supervisor did not handle the software SMI");
+            return Err(patina::error::EfiError::DeviceError);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+    use alloc::vec::Vec;
+
+    use super::*;
+    use patina::error::EfiError;
+
+    fn response(result: u64) -> Vec<u8> {
+        let mut response = Vec::new();
+        response.extend_from_slice(&u32::from_le_bytes([b'M', b'S', b'U', b'P']).to_le_bytes());
+        response.extend_from_slice(&1u32.to_le_bytes());
+        response.extend_from_slice(&0x0003u32.to_le_bytes());
+        response.extend_from_slice(&0u32.to_le_bytes());
+        response.extend_from_slice(&result.to_le_bytes());
+        response
+    }
+
+    #[test]
+    fn handled_request_is_accepted() {
+        assert!(SmmModeVerifier::check_response(&response(0)).is_ok());
+    }
+
+    #[test]
+    fn unhandled_request_is_rejected() {
+        assert_eq!(SmmModeVerifier::check_response(&response(RESULT_SENTINEL)), Err(EfiError::DeviceError));
+    }
+
+    #[test]
+    fn truncated_response_is_rejected() {
+        let response = response(0);
+        assert_eq!(SmmModeVerifier::check_response(&response[..response.len() - 1]), Err(EfiError::DeviceError));
+        assert_eq!(SmmModeVerifier::check_response(&[]), Err(EfiError::DeviceError));
+    }
+
+    #[test]
+    fn unaligned_response_is_read() {
+        let mut buffer = Vec::from([0u8]);
+        buffer.extend_from_slice(&response(RESULT_SENTINEL));
+        assert_eq!(SmmModeVerifier::check_response(&buffer[1..]), Err(EfiError::DeviceError));
+
+        let mut buffer = Vec::from([0u8]);
+        buffer.extend_from_slice(&response(0));
+        assert!(SmmModeVerifier::check_response(&buffer[1..]).is_ok());
+    }
+}
@@ -0,0 +1,59 @@
+//! QEMU Q35 Timer Frequency Service
+//!
+//! Publishes the timer frequencies determined during DXE Core initialization so that other components do not need to
+//! calibrate the TSC again.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+
+use patina::component::{Storage, component, service::IntoService};
+
+/// Provides the frequencies of the platform timers.
+pub trait TimerFrequency {
+    /// Returns the calibrated TSC frequency in Hz.
+    fn tsc_hz(&self) -> u64;
+    /// Returns the ACPI PM Timer frequency in Hz.
+    fn pm_timer_hz(&self) -> u64;
+}
+
+/// The QEMU Q35 timer frequency component.
+///
+/// Installs itself as the [`TimerFrequency`] service with the frequencies it was created with.
+#[derive(IntoService)]
+#[service(dyn TimerFrequency)]
+pub struct TimerFrequencyService {
+    tsc_hz: u64,
+    pm_timer_hz: u64,
+}
+
+#[component]
+impl TimerFrequencyService {
+    /// Creates a new instance of the QEMU Q35 timer frequency component.
+    pub fn new(tsc_hz: u64, pm_timer_hz: u64) -> Self {
+        Self { tsc_hz, pm_timer_hz }
+    }
+
+    /// Entry point for the QEMU Q35 timer frequency component.
+    fn entry_point(self, storage: &mut Storage) -> patina::error::Result<()> {
+        log::debug!("Timer Frequency Entry Point");
+        log::info!("TSC Frequency: {} Hz, PM Timer Frequency: {} Hz", self.tsc_hz, self.pm_timer_hz);
+
+        storage.add_service(self);
+
+        Ok(())
+    }
+}
+
+impl TimerFrequency for TimerFrequencyService {
+    fn tsc_hz(&self) -> u64 {
+        self.tsc_hz
+    }
+
+    fn pm_timer_hz(&self) -> u64 {
+        self.pm_timer_hz
+    }
+}
@@ -0,0 +1,42 @@
+//! QEMU Q35 Timer Frequency Test
+//!
+//! Verifies that the timer frequencies published by the timer frequency service are plausible.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+
+use patina::component::service::Service;
+use patina_test::{patina_test, u_assert, u_assert_eq};
+
+use super::timer_frequency::TimerFrequency;
+use crate::q35::timer;
+
+/// Lowest plausible TSC frequency in Hz.
+const MIN_TSC_HZ: u64 = 1_000_000_000;
+/// Highest plausible TSC frequency in Hz.
+const MAX_TSC_HZ: u64 = 5_000_000_000;
+
+/// Tests that the published TSC and PM Timer frequencies are in a plausible range.
+///
+/// The TSC is only calibrated if it is invariant, so the TSC frequency is only checked in that case.
+#[patina_test]
+fn q35_timer_frequency_test(timer_frequency: Service<dyn TimerFrequency>) -> patina_test::error::Result {
+    let tsc_hz = timer_frequency.tsc_hz();
+    let pm_timer_hz = timer_frequency.pm_timer_hz();
+    log::debug!("TSC Frequency: {tsc_hz} Hz, PM Timer Frequency: {pm_timer_hz} Hz");
+
+    u_assert_eq!(pm_timer_hz, timer::DEFAULT_ACPI_TIMER_FREQUENCY, "PM timer frequency is not 3.579545 MHz");
+
+    if timer::is_tsc_invariant() {
+        u_assert!((MIN_TSC_HZ..=MAX_TSC_HZ).contains(&tsc_hz), "TSC frequency is not between 1 GHz and 5 GHz");
+    } else {
+        log::info!("TSC is not invariant, skipping TSC frequency check");
+    }
+
+    Ok(())
+}
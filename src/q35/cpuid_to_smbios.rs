@@ -0,0 +1,275 @@
+//! CPUID to SMBIOS Processor Information Mapping
+//!
+//! Reads processor identification from CPUID and maps it to the SMBIOS Type 4 (Processor Information) encodings.
+//!
+//! ## References
+//!
+//! - [SMBIOS Reference Specification](https://www.dmtf.org/standards/smbios)
+//! - [Intel 64 and IA-32 Architectures Software Developer's Manual, Volume 2A - CPUID](https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html)
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
+
+extern crate alloc;
+use alloc::string::String;
+
+use core::arch::x86_64::__cpuid;
+use patina_smbios::smbios_types::ProcessorFamilyData;
+
+/// CPUID vendor string reported by Intel processors.
+pub const VENDOR_INTEL: &[u8; 12] = b"GenuineIntel";
+/// CPUID vendor string reported by AMD processors.
+pub const VENDOR_AMD: &[u8; 12] = b"AuthenticAMD";
+
+/// SMBIOS Type 4 `processor_family` value indicating that the family is reported in `processor_family2`.
+pub const PROCESSOR_FAMILY_INDICATOR_FAMILY2: u8 = 0xFE;
+
+/// CPUID leaf reporting processor frequency information.
+const CPUID_FREQUENCY_LEAF: u32 = 0x16;
+/// CPUID leaf reporting the maximum extended leaf.
+const CPUID_EXTENDED_MAX_LEAF: u32 = 0x8000_0000;
+/// CPUID leaf reporting extended processor features.
+const CPUID_EXTENDED_FEATURES_LEAF: u32 = 0x8000_0001;
+/// First of the three CPUID leaves reporting the processor brand string.
+const CPUID_BRAND_STRING_LEAF: u32 = 0x8000_0002;
+
+/// Intel family 6 models of Xeon server processors.
+///
+/// Lists the server variants of each microarchitecture so they can be reported as Xeon rather than as a generic Intel
+/// processor.
+const INTEL_XEON_MODELS: &[u32] = &[
+    0x1A, // Nehalem-EP
+    0x2C, // Westmere-EP
+    0x2D, // Sandy Bridge-EP
+    0x3E, // Ivy Bridge-EP
+    0x3F, // Haswell-EP
+    0x4F, // Broadwell-EP
+    0x55, // Skylake-SP / Cascade Lake-SP
+    0x6A, // Ice Lake-SP
+    0x8F, // Sapphire Rapids
+    0xCF, // Emerald Rapids
+];
+
+/// Processor identification read from CPUID.
+#[derive(Debug, Clone)]
+pub struct CpuidProcessorInfo {
+    /// Vendor identification string.
+    pub vendor: [u8; 12],
+    /// Processor brand string, if reported.
+    pub brand: Option<String>,
+    /// Processor signature (leaf 1 EAX).
+    pub signature: u32,
+    /// Feature flags (leaf 1 EDX).
+    pub feature_flags: u32,
+    /// Display family.
+    pub family: u32,
+    /// Display model.
+    pub model: u32,
+    /// Stepping ID.
+    pub stepping: u32,
+    /// Maximum number of addressable logical processors per package.
+    pub logical_processors: u8,
+    /// Base frequency in MHz, or 0 if not reported.
+    pub base_mhz: u16,
+    /// Maximum frequency in MHz, or 0 if not reported.
+    pub max_mhz: u16,
+    /// Bus (reference) frequency in MHz, or 0 if not reported.
+    pub bus_mhz: u16,
+    /// Whether the processor supports long mode.
+    pub long_mode: bool,
+    /// Whether the processor supports the execute-disable bit.
+    pub execute_disable: bool,
+}
+
+impl CpuidProcessorInfo {
+    /// Reads processor identification from CPUID on the executing processor.
+    pub fn read() -> Self {
+        let leaf0 = __cpuid(0);
+        let mut vendor = [0u8; 12];
+        vendor[0..4].copy_from_slice(&leaf0.ebx.to_le_bytes());
+        vendor[4..8].copy_from_slice(&leaf0.edx.to_le_bytes());
+        vendor[8..12].copy_from_slice(&leaf0.ecx.to_le_bytes());
+
+        let leaf1 = __cpuid(1);
+        let (family, model, stepping) = decode_signature(leaf1.eax);
+
+        let (base_mhz, max_mhz, bus_mhz) = if leaf0.eax >= CPUID_FREQUENCY_LEAF {
+            let frequency = __cpuid(CPUID_FREQUENCY_LEAF);
+            (frequency.eax as u16, frequency.ebx as u16, frequency.ecx as u16)
+        } else {
+            (0, 0, 0)
+        };
+
+        let max_extended_leaf = __cpuid(CPUID_EXTENDED_MAX_LEAF).eax;
+        let (long_mode, execute_disable) = if max_extended_leaf >= CPUID_EXTENDED_FEATURES_LEAF {
+            let extended = __cpuid(CPUID_EXTENDED_FEATURES_LEAF);
+            (extended.edx & (1 << 29) != 0, extended.edx & (1 << 20) != 0)
+        } else {
+            (false, false)
+        };
+
+        let brand = (max_extended_leaf >= CPUID_BRAND_STRING_LEAF + 2).then(|| {
+            let mut brand = [0u8; 48];
+            for (i, chunk) in brand.chunks_exact_mut(16).enumerate() {
+                let result = __cpuid(CPUID_BRAND_STRING_LEAF + i as u32);
+                chunk[0..4].copy_from_slice(&result.eax.to_le_bytes());
+                chunk[4..8].copy_from_slice(&result.ebx.to_le_bytes());
+                chunk[8..12].copy_from_slice(&result.ecx.to_le_bytes());
+                chunk[12..16].copy_from_slice(&result.edx.to_le_bytes());
+            }
+            String::from_utf8_lossy(&brand).trim_matches(|c: char| c == '\0' || c == ' ').into()
+        });
+
+        Self {
+            vendor,
+            brand,
+            signature: leaf1.eax,
+            feature_flags: leaf1.edx,
+            family,
+            model,
+            stepping,
+            logical_processors: (leaf1.ebx >> 16) as u8,
+            base_mhz,
+            max_mhz,
+            bus_mhz,
+            long_mode,
+            execute_disable,
+        }
+    }
+
+    /// Returns the SMBIOS processor ID field (leaf 1 EAX followed by leaf 1 EDX).
+    pub fn processor_id(&self) -> [u8; 8] {
+        let mut processor_id = [0u8; 8];
+        processor_id[0..4].copy_from_slice(&self.signature.to_le_bytes());
+        processor_id[4..8].copy_from_slice(&self.feature_flags.to_le_bytes());
+        processor_id
+    }
+
+    /// Returns the SMBIOS processor family.
+    pub fn processor_family(&self) -> ProcessorFamilyData {
+        processor_family(&self.vendor, self.family, self.model)
+    }
+
+    /// Returns the processor manufacturer name.
+    pub fn manufacturer(&self) -> String {
+        match &self.vendor {
+            VENDOR_INTEL => String::from("Intel(R) Corporation"),
+            VENDOR_AMD => String::from("Advanced Micro Devices, Inc."),
+            vendor => String::from_utf8_lossy(vendor).into(),
+        }
+    }
+}
+
+/// Decodes the display family, display model, and stepping from a CPUID leaf 1 EAX `signature`.
+pub fn decode_signature(signature: u32) -> (u32, u32, u32) {
+    let base_family = (signature >> 8) & 0xF;
+    let base_model = (signature >> 4) & 0xF;
+    let stepping = signature & 0xF;
+
+    let family = if base_family == 0xF { base_family + ((signature >> 20) & 0xFF) } else { base_family };
+    let model = if base_family == 0x6 || base_family == 0xF {
+        base_model | (((signature >> 16) & 0xF) << 4)
+    } else {
+        base_model
+    };
+
+    (family, model, stepping)
+}
+
+/// Maps a CPUID `vendor`, display `family`, and display `model` to the SMBIOS processor family.
+pub fn processor_family(vendor: &[u8; 12], family: u32, model: u32) -> ProcessorFamilyData {
+    match (vendor, family) {
+        (VENDOR_INTEL, 0x6) if INTEL_XEON_MODELS.contains(&model) => ProcessorFamilyData::IntelXeon,
+        (VENDOR_INTEL, 0x6) => ProcessorFamilyData::IntelProcessor,
+        (VENDOR_INTEL, 0xF) => ProcessorFamilyData::Pentium4,
+        (VENDOR_AMD, 0xF) => ProcessorFamilyData::AmdAthlon64,
+        (VENDOR_AMD, 0x10) => ProcessorFamilyData::QuadCoreAmdOpteron,
+        (VENDOR_AMD, 0x17 | 0x19 | 0x1A) => ProcessorFamilyData::AmdZen,
+        _ => ProcessorFamilyData::Other,
+    }
+}
+
+/// Returns the 1-byte SMBIOS Type 4 `processor_family` field for `family`.
+///
+/// Families that do not fit in a byte are reported as [`PROCESSOR_FAMILY_INDICATOR_FAMILY2`] so that consumers read the
+/// `processor_family2` field instead.
+pub fn processor_family_byte(family: ProcessorFamilyData) -> u8 {
+    u8::try_from(family as u16).unwrap_or(PROCESSOR_FAMILY_INDICATOR_FAMILY2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a CPUID leaf 1 EAX signature from its fields.
+    fn signature(extended_family: u32, extended_model: u32, base_family: u32, base_model: u32, stepping: u32) -> u32 {
+        (extended_family << 20) | (extended_model << 16) | (base_family << 8) | (base_model << 4) | stepping
+    }
+
+    fn family(vendor: &[u8; 12], signature: u32) -> u16 {
+        let (family, model, _) = decode_signature(signature);
+        processor_family(vendor, family, model) as u16
+    }
+
+    #[test]
+    fn intel_family_6_signature_uses_extended_model() {
+        // Sapphire Rapids: family 6, model 0x8F, stepping 8.
+        assert_eq!(decode_signature(0x000806F8), (0x6, 0x8F, 0x8));
+        assert_eq!(family(VENDOR_INTEL, 0x000806F8), ProcessorFamilyData::IntelXeon as u16);
+
+        // Alder Lake: family 6, model 0x97.
+        assert_eq!(family(VENDOR_INTEL, signature(0, 0x9, 0x6, 0x7, 0x2)), ProcessorFamilyData::IntelProcessor as u16);
+    }
+
+    #[test]
+    fn intel_family_15_signature() {
+        assert_eq!(decode_signature(signature(0, 0, 0xF, 0x4, 0x1)), (0xF, 0x4, 0x1));
+        assert_eq!(family(VENDOR_INTEL, signature(0, 0, 0xF, 0x4, 0x1)), ProcessorFamilyData::Pentium4 as u16);
+    }
+
+    #[test]
+    fn amd_zen_families_use_extended_family() {
+        // Zen 2 (EPYC Rome): family 0x17, model 0x31.
+        assert_eq!(decode_signature(0x00830F10), (0x17, 0x31, 0x0));
+        // Zen 3 (EPYC Milan): family 0x19, model 0x01.
+        assert_eq!(decode_signature(0x00A00F11), (0x19, 0x01, 0x1));
+        // Zen 5 (EPYC Turin): family 0x1A, model 0x02.
+        assert_eq!(decode_signature(0x00B00F21), (0x1A, 0x02, 0x1));
+
+        for signature in [0x00830F10, 0x00A00F11, 0x00B00F21] {
+            assert_eq!(family(VENDOR_AMD, signature), ProcessorFamilyData::AmdZen as u16);
+        }
+    }
+
+    #[test]
+    fn older_amd_families() {
+        assert_eq!(family(VENDOR_AMD, signature(0, 0, 0xF, 0x4, 0x1)), ProcessorFamilyData::AmdAthlon64 as u16);
+        assert_eq!(
+            family(VENDOR_AMD, signature(0x1, 0, 0xF, 0x2, 0x2)),
+            ProcessorFamilyData::QuadCoreAmdOpteron as u16
+        );
+    }
+
+    #[test]
+    fn non_family_6_or_15_ignores_extended_model() {
+        assert_eq!(decode_signature(signature(0, 0xF, 0x5, 0x4, 0x3)), (0x5, 0x4, 0x3));
+    }
+
+    #[test]
+    fn unknown_vendor_or_family_is_other() {
+        assert_eq!(family(b"QEMUVirtCPU ", 0x000806F8), ProcessorFamilyData::Other as u16);
+        assert_eq!(family(VENDOR_AMD, signature(0x9, 0, 0xF, 0, 0)), ProcessorFamilyData::Other as u16);
+    }
+
+    #[test]
+    fn processor_family_byte_escapes_to_family2() {
+        assert_eq!(processor_family_byte(ProcessorFamilyData::AmdZen), 0x6B);
+        assert_eq!(processor_family_byte(ProcessorFamilyData::IntelXeon), ProcessorFamilyData::IntelXeon as u8);
+        assert_eq!(processor_family_byte(ProcessorFamilyData::ARMv8), PROCESSOR_FAMILY_INDICATOR_FAMILY2);
+    }
+}
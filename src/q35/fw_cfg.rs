@@ -0,0 +1,154 @@
+//! QEMU Firmware Configuration (fw_cfg) Access
+//!
+//! Provides read access to the QEMU fw_cfg device through its legacy I/O port interface on QEMU Q35.
+//!
+//! ## References
+//!
+//! - [QEMU Firmware Configuration (fw_cfg) Device](https://www.qemu.org/docs/master/specs/fw_cfg.html)
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! SPDX-License-Identifier: Apache-2.0
+//!
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+use x86_64::instructions::port::Port;
+
+/// fw_cfg selector register I/O port.
+pub const FW_CFG_PORT_SEL: u16 = 0x510;
+/// fw_cfg data register I/O port.
+pub const FW_CFG_PORT_DATA: u16 = 0x511;
+
+/// Selector for the fw_cfg signature item.
+pub const FW_CFG_SIGNATURE: u16 = 0x0000;
+/// Selector for the RAM size item.
+pub const FW_CFG_RAM_SIZE: u16 = 0x0006;
+/// Selector for the fw_cfg file directory item.
+pub const FW_CFG_FILE_DIR: u16 = 0x0019;
+
+/// fw_cfg file containing the ACPI tables generated by QEMU.
+pub const FW_CFG_ACPI_TABLES_FILE: &str = "etc/acpi/tables";
+
+/// Size of the common ACPI table header.
+const ACPI_TABLE_HEADER_SIZE: usize = 36;
+
+/// Byte offset of the `Flags` field within the FADT (including the common ACPI table header).
+pub const FADT_FLAGS_OFFSET: usize = 112;
+
+/// Expected contents of the signature item.
+const FW_CFG_SIGNATURE_VALUE: [u8; 4] = *b"QEMU";
+
+/// Size of the name field in a file directory entry.
+const FW_CFG_FILE_NAME_SIZE: usize = 56;
+
+/// A file published in the fw_cfg file directory.
+#[derive(Debug, Clone, Copy)]
+pub struct FwCfgFile {
+    /// Selector used to read the file contents.
+    pub select: u16,
+    /// Size of the file in bytes.
+    pub size: u32,
+}
+
+/// Returns whether the fw_cfg device is present.
+pub fn is_present() -> bool {
+    let mut signature = [0u8; 4];
+    read_item(FW_CFG_SIGNATURE, &mut signature);
+    signature == FW_CFG_SIGNATURE_VALUE
+}
+
+/// Selects the item `select` and fills `buffer` from the start of the item.
+pub fn read_item(select: u16, buffer: &mut [u8]) {
+    // SAFETY: The fw_cfg selector register is a fixed I/O port on QEMU Q35.
+    unsafe { Port::<u16>::new(FW_CFG_PORT_SEL).write(select) };
+    read_data(buffer);
+}
+
+/// Looks up `name` in the fw_cfg file directory.
+pub fn find_file(name: &str) -> Option<FwCfgFile> {
+    if !is_present() {
+        return None;
+    }
+
+    let mut count = [0u8; 4];
+    read_item(FW_CFG_FILE_DIR, &mut count);
+
+    // The file directory is a big-endian count followed by that many entries, read sequentially.
+    for _ in 0..u32::from_be_bytes(count) {
+        let mut size = [0u8; 4];
+        let mut select = [0u8; 2];
+        let mut reserved = [0u8; 2];
+        let mut file_name = [0u8; FW_CFG_FILE_NAME_SIZE];
+        read_data(&mut size);
+        read_data(&mut select);
+        read_data(&mut reserved);
+        read_data(&mut file_name);
+
+        let name_len = file_name.iter().position(|&b| b == 0).unwrap_or(FW_CFG_FILE_NAME_SIZE);
+        if &file_name[..name_len] == name.as_bytes() {
+            return Some(FwCfgFile { select: u16::from_be_bytes(select), size: u32::from_be_bytes(size) });
+        }
+    }
+
+    None
+}
+
+/// Returns the guest RAM size in bytes, or `None` if the fw_cfg device is absent.
+pub fn ram_size() -> Option<u64> {
+    if !is_present() {
+        return None;
+    }
+
+    let mut ram_size = [0u8; 8];
+    read_item(FW_CFG_RAM_SIZE, &mut ram_size);
+    Some(u64::from_le_bytes(ram_size))
+}
+
+/// Returns the ACPI table with `signature` from the tables generated by QEMU, or `None` if it is not found.
+///
+/// The tables are returned as published by QEMU, before the firmware table loader patches pointers and checksums, so
+/// only fields that are not patched by the loader should be relied upon.
+pub fn find_acpi_table(signature: &[u8; 4]) -> Option<Vec<u8>> {
+    let file = find_file(FW_CFG_ACPI_TABLES_FILE)?;
+    let tables = read_file(&file);
+
+    if tables.len() < ACPI_TABLE_HEADER_SIZE {
+        return None;
+    }
+
+    // Tables are packed into the file with varying alignment, so search every offset for the signature.
+    (0..=tables.len() - ACPI_TABLE_HEADER_SIZE).find_map(|offset| {
+        let table = &tables[offset..];
+        if &table[..4] != signature {
+            return None;
+        }
+        let length = u32::from_le_bytes([table[4], table[5], table[6], table[7]]) as usize;
+        (ACPI_TABLE_HEADER_SIZE..=table.len()).contains(&length).then(|| table[..length].to_vec())
+    })
+}
+
+/// Returns the `Flags` field of the FADT `fadt`, or `None` if the table is too short to contain it.
+pub fn fadt_flags(fadt: &[u8]) -> Option<u32> {
+    fadt.get(FADT_FLAGS_OFFSET..FADT_FLAGS_OFFSET + 4)
+        .map(|flags| u32::from_le_bytes([flags[0], flags[1], flags[2], flags[3]]))
+}
+
+/// Reads the full contents of `file`.
+pub fn read_file(file: &FwCfgFile) -> Vec<u8> {
+    let mut contents = vec![0u8; file.size as usize];
+    read_item(file.select, &mut contents);
+    contents
+}
+
+fn read_data(buffer: &mut [u8]) {
+    let mut data_port = Port::<u8>::new(FW_CFG_PORT_DATA);
+    for byte in buffer.iter_mut() {
+        // SAFETY: The fw_cfg data register is a fixed I/O port on QEMU Q35.
+        *byte = unsafe { data_port.read() };
+    }
+}
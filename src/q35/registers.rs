@@ -2,10 +2,13 @@
 //!
 //! This module defines constants for QEMU Q35 register offsets and masks,
 //! including PCI Express base address and Intel I/O Controller Hub 9 (ICH9)
-//! specific registers.
+//! specific registers, along with helpers for accessing PCI Express
+//! configuration space through ECAM.
 //!
 //! ## References
 //!
+//! Section numbers in the register documentation refer to the ICH9 datasheet.
+//!
 //! - [Intel I/O Controller Hub 9 (ICH9) Datasheet](https://www.intel.com/content/dam/doc/datasheet/io-controller-hub-9-datasheet.pdf)
 //!
 //! ## License
@@ -18,20 +21,446 @@
 /// Base address for PCI Express
 pub const PCI_EXPRESS_BASE_ADDRESS: u64 = 0xB0000000;
 
+/// PCI Vendor ID register offset
+pub const PCI_VENDOR_ID: u16 = 0x00;
+/// PCI Status register offset
+pub const PCI_STATUS: u16 = 0x06;
+/// Capabilities List bit
+pub const PCI_STATUS_CAP_LIST: u16 = 0x10;
+/// Capabilities Pointer register offset
+pub const PCI_CAP_PTR: u16 = 0x34;
+/// PCI Express capability ID
+pub const PCIE_CAP_ID: u8 = 0x10;
+/// Link Status register offset (from the PCI Express capability)
+pub const PCIE_LINK_STATUS: u16 = 0x12;
+
+/// Upper bound on the number of capabilities walked, guarding against malformed (cyclic) capability lists.
+const MAX_CAPABILITIES: usize = 48;
+
+/// PCI Express link state reported by the Link Status register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcieLinkStatus {
+    /// Current link speed (1 = 2.5 GT/s, 2 = 5.0 GT/s, 3 = 8.0 GT/s, ...).
+    pub speed: u8,
+    /// Negotiated link width (number of lanes).
+    pub width: u8,
+    /// Link training is in progress.
+    pub training: bool,
+    /// Data link layer is active.
+    pub active: bool,
+}
+
+impl From<u16> for PcieLinkStatus {
+    fn from(link_status: u16) -> Self {
+        Self {
+            speed: (link_status & 0xF) as u8,
+            width: ((link_status >> 4) & 0x3F) as u8,
+            training: link_status & (1 << 11) != 0,
+            active: link_status & (1 << 13) != 0,
+        }
+    }
+}
+
+/// A PCI bus/device/function address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciBdf {
+    /// Bus number.
+    pub bus: u8,
+    /// Device number.
+    pub device: u8,
+    /// Function number.
+    pub function: u8,
+}
+
+impl PciBdf {
+    /// Creates the address of `bus`/`device`/`function`.
+    pub const fn new(bus: u8, device: u8, function: u8) -> Self {
+        Self { bus, device, function }
+    }
+
+    /// Reads the 16-bit register at `offset` in the configuration space of this function.
+    ///
+    /// # Safety
+    /// See [`ecam_read`].
+    pub unsafe fn cfg_read16(&self, offset: u16) -> u16 {
+        // SAFETY: The caller's guarantee is forwarded.
+        unsafe { ecam_read(self.bus, self.device, self.function, offset) }
+    }
+
+    /// Reads the 32-bit register at `offset` in the configuration space of this function.
+    ///
+    /// # Safety
+    /// See [`ecam_read`].
+    pub unsafe fn cfg_read32(&self, offset: u16) -> u32 {
+        // SAFETY: The caller's guarantee is forwarded.
+        unsafe { ecam_read(self.bus, self.device, self.function, offset) }
+    }
+}
+
+/// Returns the ECAM address of `offset` in the configuration space of `bus`/`dev`/`fun`.
+fn ecam_address(bus: u8, dev: u8, fun: u8, offset: u16) -> usize {
+    PCI_EXPRESS_BASE_ADDRESS as usize + patina::pci_address!(bus as u32, dev as u32, fun as u32, offset as u32) as usize
+}
+
+/// Reads the register at `offset` in the configuration space of `bus`/`dev`/`fun` through ECAM.
+///
+/// # Safety
+/// The caller must ensure the ECAM region at [`PCI_EXPRESS_BASE_ADDRESS`] is mapped and that `offset` is aligned to
+/// the size of `T` within the 4 KB configuration space.
+pub unsafe fn ecam_read<T: Copy>(bus: u8, dev: u8, fun: u8, offset: u16) -> T {
+    // SAFETY: The caller guarantees the ECAM region is mapped and the offset is aligned.
+    unsafe { core::ptr::read_volatile(ecam_address(bus, dev, fun, offset) as *const T) }
+}
+
+/// Writes `value` to the register at `offset` in the configuration space of `bus`/`dev`/`fun` through ECAM.
+///
+/// # Safety
+/// The caller must ensure the ECAM region at [`PCI_EXPRESS_BASE_ADDRESS`] is mapped, that `offset` is aligned to
+/// the size of `T` within the 4 KB configuration space, and that the write does not violate any system constraints.
+pub unsafe fn ecam_write<T: Copy>(bus: u8, dev: u8, fun: u8, offset: u16, value: T) {
+    // SAFETY: The caller guarantees the ECAM region is mapped, the offset is aligned, and the write is permitted.
+    unsafe { core::ptr::write_volatile(ecam_address(bus, dev, fun, offset) as *mut T, value) }
+}
+
+/// Returns the configuration space offset of the capability `cap_id` for `bus`/`dev`/`func`, if present.
+///
+/// # Safety
+/// The caller must ensure the ECAM region at [`PCI_EXPRESS_BASE_ADDRESS`] is mapped.
+pub unsafe fn pcie_find_capability(bus: u8, dev: u8, func: u8, cap_id: u8) -> Option<u32> {
+    // SAFETY: The caller guarantees the ECAM region is mapped.
+    find_capability(|offset| unsafe { ecam_read(bus, dev, func, offset) }, cap_id)
+}
+
+/// Walks the capability list of the configuration space read through `read16` and returns the offset of `cap_id`.
+///
+/// At most [`MAX_CAPABILITIES`] entries are visited, so a cyclic list terminates.
+fn find_capability(mut read16: impl FnMut(u16) -> u16, cap_id: u8) -> Option<u32> {
+    let status = read16(PCI_STATUS);
+    if status == u16::MAX || status & PCI_STATUS_CAP_LIST == 0 {
+        return None;
+    }
+
+    let mut offset = read16(PCI_CAP_PTR) as u8 & !0x3;
+    for _ in 0..MAX_CAPABILITIES {
+        if offset == 0 {
+            break;
+        }
+        let header = read16(offset as u16);
+        if header as u8 == cap_id {
+            return Some(offset as u32);
+        }
+        offset = (header >> 8) as u8 & !0x3;
+    }
+
+    None
+}
+
+/// Returns the PCI Express link state of `bus`/`dev`/`func`, or `None` if it has no PCI Express capability.
+///
+/// # Safety
+/// The caller must ensure the ECAM region at [`PCI_EXPRESS_BASE_ADDRESS`] is mapped.
+pub unsafe fn pcie_link_status(bus: u8, dev: u8, func: u8) -> Option<PcieLinkStatus> {
+    // SAFETY: The caller's guarantee is forwarded.
+    let cap_offset = unsafe { pcie_find_capability(bus, dev, func, PCIE_CAP_ID) }?;
+    // SAFETY: The caller guarantees the ECAM region is mapped.
+    let link_status: u16 = unsafe { ecam_read(bus, dev, func, cap_offset as u16 + PCIE_LINK_STATUS) };
+    Some(link_status.into())
+}
+
 /// Intel I/O Controller Hub 9 (ICH9) registers
 pub mod ich9 {
+    /// LPC Interface Bridge (D31:F0)
+    pub const LPC: super::PciBdf = super::PciBdf::new(0, 0x1F, 0);
     /// ICH9 Power Management Base register offset
     pub const PMBASE: u32 = 0x40;
     /// ICH9 Power Management Base register mask
     pub const PMBASE_MASK: u16 = 0xFF00;
+    /// PM1 Status offset (from PMBASE)
+    pub const PM1_STS: u16 = 0x00;
+    /// Power Button Status bit
+    pub const PWRBTN_STS: u16 = 0x0100;
+    /// PM1 Enable offset (from PMBASE)
+    pub const PM1_EN: u16 = 0x02;
+    /// Power Button Enable bit
+    pub const PWRBTN_EN: u16 = 0x0100;
+    /// PM1 Control offset (from PMBASE)
+    pub const PM1A_CNT: u16 = 0x04;
+    /// SCI Enable bit
+    pub const PM1_CNT_SCI_EN: u16 = 0x0001;
+    /// PM1 Timer offset (from PMBASE)
+    pub const PM1_TMR: u16 = 0x08;
+    /// Sleep Type field mask
+    pub const PM1_CNT_SLP_TYP_MASK: u16 = 0x1C00;
+    /// Sleep Type value for S3 (Suspend-to-RAM)
+    ///
+    /// QEMU reports SLP_TYP 1 in its DSDT `\_S3` package rather than the ICH9 datasheet encoding.
+    pub const SLP_TYP_S3: u16 = 0x0400;
+    /// Sleep Type value for S5 (Soft Off)
+    ///
+    /// QEMU reports SLP_TYP 0 in its DSDT `\_S5` package rather than the ICH9 datasheet encoding.
+    pub const SLP_TYP_S5: u16 = 0x0000;
+    /// Sleep Enable bit
+    pub const SLP_EN: u16 = 0x2000;
     /// SMI Enable offset (from PMBASE)
     pub const PMBASE_OFS_SMI_EN: u32 = 0x30;
+    /// SMI Status offset (from PMBASE)
+    pub const PMBASE_OFS_SMI_STS: u32 = 0x34;
     /// Global SMI Enable bit
     pub const SMI_EN_GBL_SMI_EN: u32 = 0x01;
     /// APMC Enable bit
     pub const SMI_EN_APMC_EN: u32 = 0x20;
+    /// Advanced Power Management Control (SMI command) I/O port
+    pub const APM_CNT: u16 = 0xB2;
     /// ICH9 General PM Control 1 register offset
     pub const GEN_PMCON_1: u32 = 0xA0;
     /// SMI Lock bit
     pub const GEN_PMCON_1_SMI_LOCK: u16 = 0x10;
+    /// GPIO Base Address register offset (13.1.14)
+    pub const GPIOBASE: u32 = 0x48;
+    /// GPIO Base Address register mask
+    pub const GPIOBASE_MASK: u16 = 0xFFC0;
+    /// GPIO Use Select offset for GPIO 0-31 (from GPIOBASE) (13.10.1)
+    pub const GPIO_USE_SEL: u16 = 0x00;
+    /// GPIO Input/Output Select offset for GPIO 0-31 (from GPIOBASE) (13.10.2)
+    pub const GP_IO_SEL: u16 = 0x04;
+    /// GPIO Level for Input or Output offset for GPIO 0-31 (from GPIOBASE) (13.10.3)
+    pub const GP_LVL: u16 = 0x0C;
+    /// GPIO Blink Enable offset for GPIO 0-31 (from GPIOBASE) (13.10.4)
+    pub const GPO_BLINK: u16 = 0x18;
+    /// GPIO Signal Invert offset for GPIO 0-31 (from GPIOBASE) (13.10.8)
+    pub const GPI_INV: u16 = 0x2C;
+    /// GPIO Use Select 2 offset for GPIO 32-63 (from GPIOBASE) (13.10.9)
+    pub const GPIO_USE_SEL2: u16 = 0x30;
+    /// GPIO Input/Output Select 2 offset for GPIO 32-63 (from GPIOBASE) (13.10.10)
+    pub const GP_IO_SEL2: u16 = 0x34;
+    /// GPIO Level for Input or Output 2 offset for GPIO 32-63 (from GPIOBASE) (13.10.11)
+    pub const GP_LVL2: u16 = 0x38;
+    /// Number of GPIO pins
+    pub const GPIO_PIN_COUNT: u8 = 64;
+
+    /// Returns the level register offset and bit mask of GPIO `pin`, or `None` if `pin` is out of range.
+    #[cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), test))]
+    pub(crate) fn gpio_level_register(pin: u8) -> Option<(u16, u32)> {
+        match pin {
+            0..32 => Some((GP_LVL, 1 << pin)),
+            32..GPIO_PIN_COUNT => Some((GP_LVL2, 1 << (pin - 32))),
+            _ => None,
+        }
+    }
+
+    /// Returns whether GPIO `pin` is high, using the GPIO I/O space at `gpio_base`.
+    ///
+    /// Returns `None` if `pin` is out of range.
+    ///
+    /// # Safety
+    /// The caller must ensure `gpio_base` is the GPIOBASE programmed in the LPC bridge and that the GPIO I/O space is
+    /// enabled in its GPIO Control register.
+    #[cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+    pub unsafe fn gpio_read_level(gpio_base: u16, pin: u8) -> Option<bool> {
+        let (offset, mask) = gpio_level_register(pin)?;
+        // SAFETY: The caller guarantees `gpio_base` is the programmed GPIOBASE. Reading the level has no side effects.
+        let level = unsafe { x86_64::instructions::port::Port::<u32>::new(gpio_base + offset).read() };
+        Some(level & mask != 0)
+    }
+
+    /// Drives GPIO `pin` high or low, using the GPIO I/O space at `gpio_base`.
+    ///
+    /// Returns `None` without writing if `pin` is out of range. The pin must be configured as a GPIO output for the
+    /// level to take effect.
+    ///
+    /// # Safety
+    /// The caller must ensure `gpio_base` is the GPIOBASE programmed in the LPC bridge, that the GPIO I/O space is
+    /// enabled in its GPIO Control register, and that driving `pin` does not violate any system constraints.
+    #[cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+    pub unsafe fn gpio_set_level(gpio_base: u16, pin: u8, high: bool) -> Option<()> {
+        let (offset, mask) = gpio_level_register(pin)?;
+        let mut level_port = x86_64::instructions::port::Port::<u32>::new(gpio_base + offset);
+        // SAFETY: The caller guarantees `gpio_base` is the programmed GPIOBASE. Only the level of `pin` is changed.
+        unsafe {
+            let level = level_port.read();
+            level_port.write(if high { level | mask } else { level & !mask });
+        }
+        Some(())
+    }
+
+    /// Layout of the start of the LPC bridge (D31:F0) configuration space (13.1).
+    ///
+    /// Only used to check the register offsets above against the datasheet at compile time. Each field has the size of
+    /// the datasheet register or register range it covers.
+    #[repr(C, packed)]
+    struct Ich9LpcConfig {
+        /// PCI configuration header (00h-3Fh).
+        header: [u8; 64],
+        pmbase: u32,
+        acpi_cntl: u8,
+        /// Reserved (45h-47h).
+        reserved_45: [u8; 3],
+        gpiobase: u32,
+        gc: u8,
+        /// Reserved (4Dh-5Fh).
+        reserved_4d: [u8; 19],
+        /// PIRQ routing, serial IRQ and LPC decode registers (60h-9Fh).
+        routing_and_decode: [u8; 64],
+        gen_pmcon_1: u16,
+    }
+
+    /// Layout of the start of the ACPI I/O register block decoded at PMBASE (13.8.3).
+    ///
+    /// Only used to check the register offsets above against the datasheet at compile time. Each field has the size of
+    /// the datasheet register or register range it covers.
+    #[repr(C, packed)]
+    struct Ich9PmIoRegs {
+        pm1_sts: u16,
+        pm1_en: u16,
+        pm1_cnt: u32,
+        pm1_tmr: u32,
+        /// Reserved (0Ch-0Fh).
+        reserved_0c: [u8; 4],
+        proc_cnt: u32,
+        /// Level 2 register and reserved (14h-1Fh).
+        reserved_14: [u8; 12],
+        gpe0_sts: [u8; 8],
+        gpe0_en: [u8; 8],
+        smi_en: u32,
+        smi_sts: u32,
+    }
+
+    const _: () = {
+        use core::mem::offset_of;
+
+        assert!(offset_of!(Ich9LpcConfig, pmbase) == PMBASE as usize);
+        assert!(offset_of!(Ich9LpcConfig, gpiobase) == GPIOBASE as usize);
+        assert!(offset_of!(Ich9LpcConfig, gen_pmcon_1) == GEN_PMCON_1 as usize);
+        assert!(GEN_PMCON_1_SMI_LOCK == 1 << 4);
+
+        assert!(offset_of!(Ich9PmIoRegs, pm1_sts) == PM1_STS as usize);
+        assert!(offset_of!(Ich9PmIoRegs, pm1_en) == PM1_EN as usize);
+        assert!(offset_of!(Ich9PmIoRegs, pm1_cnt) == PM1A_CNT as usize);
+        assert!(offset_of!(Ich9PmIoRegs, pm1_tmr) == PM1_TMR as usize);
+        assert!(offset_of!(Ich9PmIoRegs, smi_en) == PMBASE_OFS_SMI_EN as usize);
+        assert!(offset_of!(Ich9PmIoRegs, smi_sts) == PMBASE_OFS_SMI_STS as usize);
+        assert!(SMI_EN_GBL_SMI_EN == 1 << 0);
+        assert!(SMI_EN_APMC_EN == 1 << 5);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// A synthetic 256-byte configuration space with a capability list.
+    struct ConfigSpace([u8; 256]);
+
+    impl ConfigSpace {
+        /// Creates a configuration space whose capability list starts at `cap_ptr`.
+        fn with_capabilities(cap_ptr: u8) -> Self {
+            let mut config = Self([0; 256]);
+            config.write16(PCI_STATUS, PCI_STATUS_CAP_LIST);
+            config.0[PCI_CAP_PTR as usize] = cap_ptr;
+            config
+        }
+
+        fn write16(&mut self, offset: u16, value: u16) {
+            self.0[offset as usize..offset as usize + 2].copy_from_slice(&value.to_le_bytes());
+        }
+
+        /// Adds capability `cap_id` at `offset` pointing to the capability at `next`.
+        fn capability(mut self, offset: u8, cap_id: u8, next: u8) -> Self {
+            self.write16(offset as u16, u16::from_le_bytes([cap_id, next]));
+            self
+        }
+
+        fn read16(&self, offset: u16) -> u16 {
+            u16::from_le_bytes([self.0[offset as usize], self.0[offset as usize + 1]])
+        }
+
+        fn find(&self, cap_id: u8) -> Option<u32> {
+            find_capability(|offset| self.read16(offset), cap_id)
+        }
+    }
+
+    #[test]
+    fn capability_is_found_in_list() {
+        let config = ConfigSpace::with_capabilities(0x40).capability(0x40, 0x01, 0x50).capability(0x50, PCIE_CAP_ID, 0);
+        assert_eq!(config.find(0x01), Some(0x40));
+        assert_eq!(config.find(PCIE_CAP_ID), Some(0x50));
+    }
+
+    #[test]
+    fn missing_capability_is_not_found() {
+        let config = ConfigSpace::with_capabilities(0x40).capability(0x40, 0x01, 0x50).capability(0x50, 0x05, 0);
+        assert_eq!(config.find(PCIE_CAP_ID), None);
+    }
+
+    #[test]
+    fn function_without_capability_list_is_skipped() {
+        let mut config = ConfigSpace::with_capabilities(0x40).capability(0x40, PCIE_CAP_ID, 0);
+        config.write16(PCI_STATUS, 0);
+        assert_eq!(config.find(PCIE_CAP_ID), None);
+
+        assert_eq!(find_capability(|_| u16::MAX, PCIE_CAP_ID), None);
+    }
+
+    #[test]
+    fn reserved_pointer_bits_are_ignored() {
+        let config = ConfigSpace::with_capabilities(0x43).capability(0x40, 0x01, 0x53).capability(0x50, PCIE_CAP_ID, 0);
+        assert_eq!(config.find(PCIE_CAP_ID), Some(0x50));
+    }
+
+    #[test]
+    fn self_pointing_capability_terminates() {
+        let config = ConfigSpace::with_capabilities(0x40).capability(0x40, 0x01, 0x40);
+        assert_eq!(config.find(PCIE_CAP_ID), None);
+    }
+
+    #[test]
+    fn cyclic_capability_list_terminates() {
+        let config = ConfigSpace::with_capabilities(0x40).capability(0x40, 0x01, 0x50).capability(0x50, 0x05, 0x40);
+        assert_eq!(config.find(PCIE_CAP_ID), None);
+    }
+
+    #[test]
+    fn walk_is_capped_at_max_capabilities() {
+        let config = ConfigSpace::with_capabilities(0x40).capability(0x40, 0x01, 0x40);
+        let reads = Cell::new(0);
+        let found = find_capability(
+            |offset| {
+                reads.set(reads.get() + 1);
+                config.read16(offset)
+            },
+            PCIE_CAP_ID,
+        );
+        assert_eq!(found, None);
+        // Status, capabilities pointer, then one header per visited capability.
+        assert_eq!(reads.get(), 2 + MAX_CAPABILITIES);
+    }
+
+    #[test]
+    fn last_capability_within_cap_is_found() {
+        // Fill every dword from 0x40 to 0xFC, the longest list that fits in the configuration space.
+        let mut config = ConfigSpace::with_capabilities(0x40);
+        for offset in (0x40..0xFC).step_by(4) {
+            config = config.capability(offset, 0x01, offset + 4);
+        }
+        config = config.capability(0xFC, PCIE_CAP_ID, 0);
+        assert_eq!((0x40..=0xFC).step_by(4).count(), MAX_CAPABILITIES);
+        assert_eq!(config.find(PCIE_CAP_ID), Some(0xFC));
+    }
+
+    #[test]
+    fn gpio_pins_map_to_level_registers() {
+        use ich9::{GP_LVL, GP_LVL2, gpio_level_register};
+
+        assert_eq!(gpio_level_register(0), Some((GP_LVL, 1)));
+        assert_eq!(gpio_level_register(31), Some((GP_LVL, 1 << 31)));
+        assert_eq!(gpio_level_register(32), Some((GP_LVL2, 1)));
+        assert_eq!(gpio_level_register(63), Some((GP_LVL2, 1 << 31)));
+    }
+
+    #[test]
+    fn out_of_range_gpio_pins_are_rejected() {
+        assert_eq!(ich9::gpio_level_register(ich9::GPIO_PIN_COUNT), None);
+        assert_eq!(ich9::gpio_level_register(u8::MAX), None);
+    }
 }
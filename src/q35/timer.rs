@@ -1,11 +1,13 @@
 //! QEMU Q35 Timer Calibration
 //!
 //! This module provides functionality to calibrate the tick frequency on
-//! QEMU Q35 platforms using the ACPI Power Management Timer (PM Timer).
+//! QEMU Q35 platforms using the ACPI Power Management Timer (PM Timer), with
+//! the High Precision Event Timer (HPET) as a fallback.
 //!
 //! ## References
 //!
 //! - [ACPI PM Timer](https://uefi.org/specs/ACPI/6.5/04_ACPI_Hardware_Specification.html)
+//! - [IA-PC HPET Specification](https://www.intel.com/content/dam/www/public/us/en/documents/technical-specifications/software-developers-hpet-spec-1-0a.pdf)
 //! - [FADT Table Definition](https://uefi.org/htmlspecs/ACPI_Spec_6_4_html/05_ACPI_Software_Programming_Model/ACPI_Software_Programming_Model.html#fixed-acpi-description-table-fadt)
 //!
 //! ## License
@@ -14,30 +16,213 @@
 //!
 //! SPDX-License-Identifier: Apache-2.0
 //!
-#![cfg(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"))]
+#![cfg(any(all(target_os = "uefi", target_arch = "x86_64", feature = "x64"), all(test, target_arch = "x86_64")))]
 
-use core::arch::x86_64;
+use core::{
+    arch::x86_64,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-const DEFAULT_ACPI_TIMER_FREQUENCY: u64 = 3_579_545; // 3.579545 MHz
+/// Frequency of the ACPI PM Timer in Hz.
+pub const DEFAULT_ACPI_TIMER_FREQUENCY: u64 = 3_579_545; // 3.579545 MHz
 
-/// Calibrates the TSC frequency using the ACPI PM Timer.
+/// Mask of a 24-bit PM Timer value. The ICH9 PM Timer emulated by QEMU is always 24 bits wide.
+const PM_TIMER_MASK_24_BIT: u64 = 0x00FF_FFFF;
+/// CPUID leaf reporting the maximum extended leaf.
+const CPUID_EXTENDED_MAX_LEAF: u32 = 0x8000_0000;
+/// CPUID leaf reporting advanced power management information.
+const CPUID_ADVANCED_POWER_MANAGEMENT_LEAF: u32 = 0x8000_0007;
+/// Invariant TSC bit in CPUID leaf 0x80000007 EDX.
+const CPUID_INVARIANT_TSC: u32 = 1 << 8;
+
+/// TSC frequency determined by [`cached_tsc_frequency`], or 0 until it is first calibrated.
+static TSC_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// MMIO base address of the HPET on QEMU Q35.
+pub const HPET_BASE_ADDRESS: usize = 0xFED0_0000;
+
+/// HPET General Capabilities and ID register offset.
+const HPET_CAPABILITIES: usize = 0x000;
+/// HPET General Configuration register offset.
+const HPET_CONFIGURATION: usize = 0x010;
+/// HPET Main Counter Value register offset.
+const HPET_MAIN_COUNTER: usize = 0x0F0;
+/// Counter Size capability bit (set if the main counter is 64 bits wide).
+const HPET_CAP_COUNT_SIZE: u64 = 1 << 13;
+/// Overall Enable configuration bit.
+const HPET_CFG_ENABLE: u64 = 1 << 0;
+/// Largest counter tick period in femtoseconds permitted by the HPET specification (100 ns).
+const HPET_MAX_PERIOD_FS: u64 = 100_000_000;
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+/// A timer with a known frequency used to measure the TSC frequency.
+#[derive(Debug, Clone, Copy)]
+pub enum CalibrationSource {
+    /// The ACPI PM Timer at the given I/O port.
+    PmTimer(u16),
+    /// The HPET with its register block mapped at the given address.
+    Hpet(*const u8),
+}
+
+/// Calibrates the TSC frequency using the first of `sources` that completes a measurement.
+///
+/// Each source is tried in order, falling back to the next if the measurement times out. The measurement is skipped
+/// if the TSC is not invariant, as the TSC may then not tick at a constant rate.
+///
+/// Equivalent to [`calibrate_tsc_frequency_averaged`] with a single sample.
+///
+/// # Safety
+/// This function performs raw I/O port and MMIO access, which is inherently unsafe. The caller must ensure that each
+/// of the provided `sources` is valid and that accessing it does not violate any system constraints.
+pub unsafe fn calibrate_tsc_frequency(sources: &[CalibrationSource]) -> u64 {
+    // Safety: The caller's guarantee is forwarded.
+    unsafe { calibrate_tsc_frequency_averaged(sources, 1) }
+}
+
+/// Calibrates the TSC frequency as the mean of `samples` measurements.
+///
+/// A single measurement may be skewed if the virtual machine is descheduled by the host during the measurement window.
+/// If at least three measurements complete, the highest and lowest are discarded before averaging. Each measurement
+/// takes about 50 ms, so 5 samples add about 250 ms to boot.
 ///
 /// # Safety
-/// This function performs raw I/O port access, which is inherently unsafe. The caller must ensure
-/// that the provided `pm_timer_port` is valid and that reading from this port does not violate any system constraints.
-pub unsafe fn calibrate_tsc_frequency(pm_timer_port: u16) -> u64 {
+/// This function performs raw I/O port and MMIO access, which is inherently unsafe. The caller must ensure that each
+/// of the provided `sources` is valid and that accessing it does not violate any system constraints.
+pub unsafe fn calibrate_tsc_frequency_averaged(sources: &[CalibrationSource], samples: usize) -> u64 {
+    if !is_tsc_invariant() {
+        log::warn!("TSC is not invariant, skipping TSC calibration");
+        return DEFAULT_ACPI_TIMER_FREQUENCY;
+    }
+    log::debug!("Calibrating invariant TSC frequency");
+
+    let mut count = 0u64;
+    let mut sum = 0u64;
+    let mut min = u64::MAX;
+    let mut max = 0u64;
+    for _ in 0..samples.max(1) {
+        // Safety: The provided sources must be valid per the function's safety contract.
+        let Some(frequency) = sources.iter().find_map(|source| {
+            let frequency = unsafe { measure_tsc_frequency(*source) };
+            if frequency.is_none() {
+                log::warn!("TSC calibration using {source:?} failed");
+            }
+            frequency
+        }) else {
+            continue;
+        };
+
+        count += 1;
+        sum += frequency;
+        min = min.min(frequency);
+        max = max.max(frequency);
+    }
+
+    match count {
+        // This default value is not accurate, but allows the system to proceed and gather relative timings still.
+        0 => DEFAULT_ACPI_TIMER_FREQUENCY,
+        1 | 2 => sum / count,
+        _ => (sum - min - max) / (count - 2),
+    }
+}
+
+/// Returns the TSC frequency, calibrating it with [`calibrate_tsc_frequency_averaged`] on first use.
+///
+/// Later calls return the first result without measuring again, so the performance timer and any component that needs
+/// the TSC frequency share a single calibration.
+///
+/// # Safety
+/// This function performs raw I/O port and MMIO access, which is inherently unsafe. The caller must ensure that each
+/// of the provided `sources` is valid and that accessing it does not violate any system constraints.
+pub unsafe fn cached_tsc_frequency(sources: &[CalibrationSource], samples: usize) -> u64 {
+    match TSC_FREQUENCY.load(Ordering::Relaxed) {
+        0 => {
+            // Safety: The caller's guarantee is forwarded.
+            let frequency = unsafe { calibrate_tsc_frequency_averaged(sources, samples) };
+            TSC_FREQUENCY.store(frequency, Ordering::Relaxed);
+            frequency
+        }
+        frequency => frequency,
+    }
+}
+
+/// Measures the TSC frequency against `source`, or returns `None` if `source` is absent or not counting.
+///
+/// # Safety
+/// The caller must ensure that `source` is valid.
+unsafe fn measure_tsc_frequency(source: CalibrationSource) -> Option<u64> {
+    match source {
+        CalibrationSource::PmTimer(port) => {
+            // Safety: The provided PM timer port must be valid per the function's safety contract.
+            measure_against(
+                || unsafe { read_pm_timer(port) } as u64,
+                PM_TIMER_MASK_24_BIT,
+                DEFAULT_ACPI_TIMER_FREQUENCY,
+            )
+        }
+        CalibrationSource::Hpet(base) => {
+            // Safety: The provided HPET base must be valid per the function's safety contract.
+            let capabilities = unsafe { core::ptr::read_volatile(base.add(HPET_CAPABILITIES) as *const u64) };
+            let period_fs = capabilities >> 32;
+            if period_fs == 0 || period_fs > HPET_MAX_PERIOD_FS {
+                log::warn!("HPET at {base:p} reports an invalid counter period {period_fs:#X}");
+                return None;
+            }
+
+            // Safety: The provided HPET base must be valid per the function's safety contract.
+            unsafe {
+                let configuration = base.add(HPET_CONFIGURATION) as *mut u64;
+                let value = core::ptr::read_volatile(configuration);
+                if value & HPET_CFG_ENABLE == 0 {
+                    core::ptr::write_volatile(configuration, value | HPET_CFG_ENABLE);
+                }
+            }
+
+            let frequency = FEMTOSECONDS_PER_SECOND / period_fs;
+            // Safety: The main counter is within the HPET register block.
+            let counter = unsafe { base.add(HPET_MAIN_COUNTER) };
+            if capabilities & HPET_CAP_COUNT_SIZE != 0 {
+                // Safety: The provided HPET base must be valid per the function's safety contract.
+                measure_against(|| unsafe { core::ptr::read_volatile(counter as *const u64) }, u64::MAX, frequency)
+            } else {
+                // Safety: The provided HPET base must be valid per the function's safety contract.
+                measure_against(
+                    || unsafe { core::ptr::read_volatile(counter as *const u32) } as u64,
+                    u32::MAX as u64,
+                    frequency,
+                )
+            }
+        }
+    }
+}
+
+/// Measures the TSC frequency against a free-running counter read by `read_counter` that wraps at `counter_mask`
+/// and ticks at `counter_frequency` Hz.
+fn measure_against(read_counter: impl Fn() -> u64, counter_mask: u64, counter_frequency: u64) -> Option<u64> {
+    // SAFETY: `_rdtsc` is a leaf intrinsic that reads the processor's timestamp counter.
+    // It has no memory or pointer safety implications. The only requirement is that the
+    // caller accepts that `RDTSC` is not serializing and may be affected by out-of-order
+    // execution, but this does not impact Rust's safety guarantees.
+    measure_tsc_against(read_counter, || unsafe { x86_64::_rdtsc() }, counter_mask, counter_frequency)
+}
+
+/// Measures the rate of the counter read by `read_tsc` against a free-running counter read by `read_counter` that
+/// wraps at `counter_mask` and ticks at `counter_frequency` Hz.
+fn measure_tsc_against(
+    read_counter: impl Fn() -> u64,
+    read_tsc: impl Fn() -> u64,
+    counter_mask: u64,
+    counter_frequency: u64,
+) -> Option<u64> {
     // If there is an issue with the timer calibration loop, avoid hanging forever.
     const MAX_WAIT_CYCLES: usize = 1_000_000;
 
-    // Wait for a PM timer edge to avoid partial intervals.
-    // Safety: The provided PM timer port must be valid per the function's safety contract.
-    let mut start_pm = unsafe { read_pm_timer(pm_timer_port) };
-    let mut next_pm;
+    // Wait for a counter edge to avoid partial intervals.
+    let mut start_count = read_counter();
+    let mut next_count;
     let mut calibration_cycles_left = MAX_WAIT_CYCLES;
     loop {
-        // Safety: The provided PM timer port must be valid per the function's safety contract.
-        next_pm = unsafe { read_pm_timer(pm_timer_port) };
-        if next_pm != start_pm {
+        next_count = read_counter();
+        if next_count != start_count {
             break;
         }
 
@@ -45,58 +230,86 @@ pub unsafe fn calibrate_tsc_frequency(pm_timer_port: u16) -> u64 {
         // Avoid an infinite hang by breaking after too many cycles.
         // This means timer calibration may not be fully accurate, but can still safely proceed.
         if calibration_cycles_left == 0 {
-            log::warn!("PM timer calibration timeout waiting for edge");
+            log::warn!("Timer calibration timeout waiting for edge");
             break;
         }
     }
-    start_pm = next_pm;
+    start_count = next_count;
 
     // Record starting TSC.
-    // SAFETY: `_rdtsc` is a leaf intrinsic that reads the processor's timestamp counter.
-    // It has no memory or pointer safety implications. The only requirement is that the
-    // caller accepts that `RDTSC` is not serializing and may be affected by out-of-order
-    // execution, but this does not impact Rust's safety guarantees.
-    let start_tsc = unsafe { x86_64::_rdtsc() };
+    let start_tsc = read_tsc();
 
     // Hz = ticks/second. Divided by 20 ~ ticks / 50 ms.
     const TARGET_INTERVAL_SIZE: u64 = 20;
-    let target_ticks = (DEFAULT_ACPI_TIMER_FREQUENCY / TARGET_INTERVAL_SIZE) as u32;
+    // The interval must be shorter than half the counter period for rollover to be handled correctly.
+    let target_ticks = (counter_frequency / TARGET_INTERVAL_SIZE).min(counter_mask / 2);
 
-    let mut end_pm;
+    let mut delta_count;
     calibration_cycles_left = MAX_WAIT_CYCLES;
     loop {
-        // Safety: The provided PM timer port must be valid per the function's safety contract.
-        end_pm = unsafe { read_pm_timer(pm_timer_port) };
-        let delta = end_pm.wrapping_sub(start_pm);
-        if delta >= target_ticks {
+        delta_count = read_counter().wrapping_sub(start_count) & counter_mask;
+        if delta_count >= target_ticks {
             break;
         }
         calibration_cycles_left -= 1;
-        // If the PM timer is malfunctioning or not supported, avoid an infinite hang by breaking after too many cycles.
-        // In this case we cannot safely proceed as will cause a zero division error, so we return a default value.
-        // This default value is not accurate, but allows the system to proceed and gather relative timings still.
+        // If the timer is malfunctioning or not supported, avoid an infinite hang by breaking after too many cycles.
+        // In this case we cannot safely proceed as will cause a zero division error.
         if calibration_cycles_left == 0 {
-            log::warn!("PM timer calibration timeout waiting for target ticks");
-            return DEFAULT_ACPI_TIMER_FREQUENCY;
+            log::warn!("Timer calibration timeout waiting for target ticks");
+            return None;
         }
     }
 
     // Record ending TSC.
-    // SAFETY: `_rdtsc` is a leaf intrinsic that reads the processor's timestamp counter.
-    // It has no memory or pointer safety implications. The only requirement is that the
-    // caller accepts that `RDTSC` is not serializing and may be affected by out-of-order
-    // execution, but this does not impact Rust's safety guarantees.
-    let end_tsc = unsafe { x86_64::_rdtsc() };
+    let end_tsc = read_tsc();
 
-    // Time elapsed based on PM timer ticks.
-    let delta_pm = end_pm.wrapping_sub(start_pm) as u64;
-    let delta_time_ns = (delta_pm * 1_000_000_000) / DEFAULT_ACPI_TIMER_FREQUENCY;
+    // Time elapsed based on counter ticks.
+    let delta_time_ns = (delta_count as u128 * 1_000_000_000) / counter_frequency as u128;
 
     // Rdtsc ticks.
     let delta_tsc = end_tsc - start_tsc;
 
     // Frequency = Rdtsc ticks / elapsed time.
-    (delta_tsc * 1_000_000_000) / delta_time_ns
+    Some(((delta_tsc as u128 * 1_000_000_000) / delta_time_ns) as u64)
+}
+
+/// Returns whether the processor reports an invariant TSC that ticks at a constant rate.
+pub fn is_tsc_invariant() -> bool {
+    is_tsc_invariant_with(x86_64::__cpuid)
+}
+
+/// Returns whether the CPUID results returned by `cpuid` for a leaf report an invariant TSC.
+fn is_tsc_invariant_with(cpuid: fn(u32) -> x86_64::CpuidResult) -> bool {
+    if cpuid(CPUID_EXTENDED_MAX_LEAF).eax < CPUID_ADVANCED_POWER_MANAGEMENT_LEAF {
+        return false;
+    }
+    cpuid(CPUID_ADVANCED_POWER_MANAGEMENT_LEAF).edx & CPUID_INVARIANT_TSC != 0
+}
+
+/// Busy-waits for at least `microseconds` using the ACPI PM Timer.
+///
+/// # Safety
+/// This function performs raw I/O port access, which is inherently unsafe. The caller must ensure that
+/// the provided `pm_timer_port` is valid and that reading from this port does not violate any system constraints.
+pub unsafe fn stall(pm_timer_port: u16, microseconds: u64) {
+    // If the PM timer is not counting, avoid hanging forever.
+    const MAX_WAIT_CYCLES: usize = 10_000_000;
+
+    let target_ticks = (microseconds * DEFAULT_ACPI_TIMER_FREQUENCY).div_ceil(1_000_000);
+    // Safety: The provided PM timer port must be valid per the function's safety contract.
+    let mut last_pm = unsafe { read_pm_timer(pm_timer_port) };
+    let mut elapsed_ticks = 0u64;
+    for _ in 0..MAX_WAIT_CYCLES {
+        if elapsed_ticks >= target_ticks {
+            return;
+        }
+        // Safety: The provided PM timer port must be valid per the function's safety contract.
+        let now_pm = unsafe { read_pm_timer(pm_timer_port) };
+        // Consecutive reads are far less than a 24-bit rollover period apart, so the mask is valid in either mode.
+        elapsed_ticks += now_pm.wrapping_sub(last_pm) as u64 & PM_TIMER_MASK_24_BIT;
+        last_pm = now_pm;
+    }
+    log::warn!("PM timer stall timeout");
 }
 
 /// Reads the current value of the ACPI PM Timer from the specified I/O port.
@@ -117,3 +330,96 @@ unsafe fn read_pm_timer(pm_timer_port: u16) -> u32 {
     }
     value
 }
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    /// Mask of a 32-bit timer value.
+    const PM_TIMER_MASK_32_BIT: u64 = 0xFFFF_FFFF;
+
+    /// TSC ticks per PM Timer tick of the simulated processor.
+    const TSC_RATIO: u64 = 1000;
+
+    /// Measures a simulated TSC against a simulated PM Timer that starts at `start` and advances one tick per read.
+    fn measure_simulated(start: u64, counter_mask: u64) -> Option<u64> {
+        let time = Cell::new(start);
+        let read_counter = || {
+            let now = time.get();
+            time.set(now + 1);
+            now & counter_mask
+        };
+        let read_tsc = || time.get() * TSC_RATIO;
+        measure_tsc_against(read_counter, read_tsc, counter_mask, DEFAULT_ACPI_TIMER_FREQUENCY)
+    }
+
+    fn assert_close(frequency: u64) {
+        let expected = DEFAULT_ACPI_TIMER_FREQUENCY * TSC_RATIO;
+        assert!(frequency.abs_diff(expected) < expected / 10_000, "measured {frequency}, expected {expected}");
+    }
+
+    #[test]
+    fn measures_24_bit_counter() {
+        assert_close(measure_simulated(0x10_0000, PM_TIMER_MASK_24_BIT).unwrap());
+    }
+
+    #[test]
+    fn measures_24_bit_counter_across_rollover() {
+        assert_close(measure_simulated(PM_TIMER_MASK_24_BIT - 0x100, PM_TIMER_MASK_24_BIT).unwrap());
+    }
+
+    #[test]
+    fn measures_32_bit_counter() {
+        assert_close(measure_simulated(0x1000_0000, PM_TIMER_MASK_32_BIT).unwrap());
+    }
+
+    #[test]
+    fn measures_32_bit_counter_across_rollover() {
+        assert_close(measure_simulated(PM_TIMER_MASK_32_BIT - 0x100, PM_TIMER_MASK_32_BIT).unwrap());
+    }
+
+    #[test]
+    fn stopped_counter_times_out() {
+        assert_eq!(measure_tsc_against(|| 0x1234, || 0, PM_TIMER_MASK_24_BIT, DEFAULT_ACPI_TIMER_FREQUENCY), None);
+    }
+
+    fn cpuid_result(eax: u32, edx: u32) -> x86_64::CpuidResult {
+        x86_64::CpuidResult { eax, ebx: 0, ecx: 0, edx }
+    }
+
+    #[test]
+    fn invariant_tsc_is_detected() {
+        fn cpuid(leaf: u32) -> x86_64::CpuidResult {
+            match leaf {
+                CPUID_EXTENDED_MAX_LEAF => cpuid_result(0x8000_0008, 0),
+                CPUID_ADVANCED_POWER_MANAGEMENT_LEAF => cpuid_result(0, CPUID_INVARIANT_TSC),
+                _ => cpuid_result(0, 0),
+            }
+        }
+        assert!(is_tsc_invariant_with(cpuid));
+    }
+
+    #[test]
+    fn variant_tsc_is_detected() {
+        fn cpuid(leaf: u32) -> x86_64::CpuidResult {
+            match leaf {
+                CPUID_EXTENDED_MAX_LEAF => cpuid_result(0x8000_0008, 0),
+                _ => cpuid_result(0, !CPUID_INVARIANT_TSC),
+            }
+        }
+        assert!(!is_tsc_invariant_with(cpuid));
+    }
+
+    #[test]
+    fn missing_power_management_leaf_is_not_invariant() {
+        fn cpuid(leaf: u32) -> x86_64::CpuidResult {
+            match leaf {
+                CPUID_EXTENDED_MAX_LEAF => cpuid_result(0x8000_0004, 0),
+                _ => cpuid_result(0, CPUID_INVARIANT_TSC),
+            }
+        }
+        assert!(!is_tsc_invariant_with(cpuid));
+    }
+}